@@ -1,5 +1,7 @@
 //! ASCII utilities
 
+use core::ptr;
+
 /// Returns `Ok` if the byte-slice is ascii, and the largest index for which
 /// `is_ascii(&[..index])` returns `Ok`, that is, the index of the first
 /// non-ASCII byte.
@@ -10,39 +12,44 @@ pub fn is_ascii_scalar(x: &[u8]) -> Result<(), usize> {
     Ok(())
 }
 
+/// Portable 128-bit vector algorithm: tests 16 bytes at a time as a `u128`.
 pub fn is_ascii_vector128(s: &[u8]) -> Result<(), usize> {
-    use ::simd::*;
+    const LANES: usize = 16;
     let mut i = 0;
-    let v128 = u8x16::splat(128);
-    let zero = u8x16::splat(0);
+    let v128 = u128::from_ne_bytes([128; LANES]);
+    let ptr = s.as_ptr();
     let len = s.len();
-    while i + u8x16::lanes() * 2 <= len {
-        let x = unsafe { u8x16::load_unaligned_unchecked(&s.get_unchecked(i..)) };
-        let y = unsafe { u8x16::load_unaligned_unchecked(&s.get_unchecked(i + u8x16::lanes()..)) };
-        let x: u8x16 = x & v128;
-        let y: u8x16 = y & v128;
-        if !x.eq(zero).all() || !y.eq(zero).all() {
+    while i + LANES * 2 <= len {
+        let x = unsafe { ptr::read_unaligned(ptr.add(i) as *const u128) };
+        let y = unsafe {
+            ptr::read_unaligned(ptr.add(i + LANES) as *const u128)
+        };
+        if x & v128 != 0 || y & v128 != 0 {
             break;
         }
-        i += u8x16::lanes() * 2;
+        i += LANES * 2;
     }
-    is_ascii_scalar(unsafe { &s.get_unchecked(i..) }).map_err(|e| e + i)
+    is_ascii_scalar(unsafe { s.get_unchecked(i..) }).map_err(|e| e + i)
 }
 
 /// _mm_testz_si128 requires SSE4.1
+///
+/// # Safety
+///
+/// The CPU must support SSE4.1.
 #[target_feature(enable = "sse4.1")]
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub unsafe fn is_ascii_vector128_sse41(x: &[u8]) -> Result<(), usize> {
     use ::arch::*;
     let mut i = 0;
-    let signbitmask = _mm_set1_epi8(::mem::transmute(0b1000_0000_u8));
+    let signbitmask = _mm_set1_epi8(0b1000_0000_u8 as i8);
     let ptr = x.as_ptr();
     let len = x.len();
     while i + 64 <= len {
-        let x0 = _mm_loadu_si128(ptr.offset(i as isize) as *const __m128i);
-        let x1 = _mm_loadu_si128(ptr.offset(i as isize + 16) as *const __m128i);
-        let x2 = _mm_loadu_si128(ptr.offset(i as isize + 32) as *const __m128i);
-        let x3 = _mm_loadu_si128(ptr.offset(i as isize + 48) as *const __m128i);
+        let x0 = _mm_loadu_si128(ptr.add(i) as *const __m128i);
+        let x1 = _mm_loadu_si128(ptr.add(i + 16) as *const __m128i);
+        let x2 = _mm_loadu_si128(ptr.add(i + 32) as *const __m128i);
+        let x3 = _mm_loadu_si128(ptr.add(i + 48) as *const __m128i);
         if _mm_testz_si128(x0, signbitmask) == 0
             || _mm_testz_si128(x1, signbitmask) == 0
             || _mm_testz_si128(x2, signbitmask) == 0
@@ -54,19 +61,24 @@ pub unsafe fn is_ascii_vector128_sse41(x: &[u8]) -> Result<(), usize> {
     is_ascii_scalar(&x[i..]).map_err(|e| e + i)
 }
 
+/// _mm256_testz_si256 requires AVX
+///
+/// # Safety
+///
+/// The CPU must support AVX.
 #[target_feature(enable = "avx")]
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub unsafe fn is_ascii_vector256_avx(x: &[u8]) -> Result<(), usize> {
     use ::arch::*;
     let mut i = 0;
-    let signbitmask = _mm256_set1_epi8(::mem::transmute(0b1000_0000_u8));
+    let signbitmask = _mm256_set1_epi8(0b1000_0000_u8 as i8);
     let ptr = x.as_ptr();
     let len = x.len();
     while i + 128 <= len {
-        let x0 = _mm256_loadu_si256(ptr.offset(i as isize) as *const __m256i);
-        let x1 = _mm256_loadu_si256(ptr.offset(i as isize + 32) as *const __m256i);
-        let x2 = _mm256_loadu_si256(ptr.offset(i as isize + 64) as *const __m256i);
-        let x3 = _mm256_loadu_si256(ptr.offset(i as isize + 96) as *const __m256i);
+        let x0 = _mm256_loadu_si256(ptr.add(i) as *const __m256i);
+        let x1 = _mm256_loadu_si256(ptr.add(i + 32) as *const __m256i);
+        let x2 = _mm256_loadu_si256(ptr.add(i + 64) as *const __m256i);
+        let x3 = _mm256_loadu_si256(ptr.add(i + 96) as *const __m256i);
         if _mm256_testz_si256(x0, signbitmask) == 0
             || _mm256_testz_si256(x1, signbitmask) == 0
             || _mm256_testz_si256(x2, signbitmask) == 0
@@ -82,6 +94,7 @@ pub unsafe fn is_ascii_vector256_avx(x: &[u8]) -> Result<(), usize> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::prelude::v1::*;
 
     fn test_is_slice_ascii<F>(f: F)
         where F: Fn(&[u8]) -> Result<(), usize>
@@ -91,19 +104,19 @@ mod tests {
                 let v = (i..j).collect::<Vec<u8>>();
                 assert!(f(v.as_slice()).is_ok());
             }
-            for j in 128..=u8::max_value() {
+            for j in 128..=u8::MAX {
                 let v = (i..=j).collect::<Vec<u8>>();
                 let r = f(v.as_slice());
                 assert!(r.is_err());
                 assert_eq!(r.unwrap_err(), 128 - i as usize);
             }
         }
-        for i in 128..=u8::max_value() {
-            for j in i..=u8::max_value() {
+        for i in 128..=u8::MAX {
+            for j in i..=u8::MAX {
                 let v = (i..=j).collect::<Vec<u8>>();
                 let r = f(v.as_slice());
                 assert!(r.is_err());
-                assert_eq!(r.unwrap_err(), 0 as usize);
+                assert_eq!(r.unwrap_err(), 0);
             }
         }
     }
@@ -117,6 +130,10 @@ mod tests {
     fn test_is_ascii_vector128() {
         test_is_slice_ascii(is_ascii_vector128);
     }
+    #[test]
+    fn test_is_ascii() {
+        test_is_slice_ascii(|x| ::is_ascii(x).map_err(|e| e.valid_up_to()));
+    }
     #[cfg(all(any(target_arch = "x86", target_arch = "x86_64"), target_feature = "sse4.1"))]
     #[test]
     fn test_is_ascii_vector128_sse41() {
@@ -0,0 +1,323 @@
+//! Asynchronous adapters for tokio's `AsyncRead` and for streams of `Bytes`.
+
+use bytes::Bytes;
+use futures_core::Stream;
+use io::ReadState;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, ReadBuf};
+use {Error, Utf8Validator};
+
+/// An `AsyncRead` that validates the bytes it reads from `R`.
+///
+/// The asynchronous counterpart of `Utf8Reader`. Like `Utf8Reader::fill_buf`
+/// and unlike `Utf8Reader::read`, it hands out the leading bytes of a
+/// character split across reads before the rest of the character is read,
+/// so up to 3 bytes of an invalid or truncated sequence may precede the
+/// error.
+#[derive(Debug)]
+pub struct AsyncUtf8Reader<R> {
+    inner: R,
+    state: ReadState,
+}
+
+impl<R> AsyncUtf8Reader<R> {
+    /// Creates a reader that validates the bytes read from `inner`.
+    pub fn new(inner: R) -> Self {
+        AsyncUtf8Reader {
+            inner,
+            state: ReadState::default(),
+        }
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying reader.
+    ///
+    /// Bytes read directly from the underlying reader are not validated.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncUtf8Reader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(e) = this.state.error() {
+            return Poll::Ready(Err(e.into()));
+        }
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let before = buf.filled().len();
+        match Pin::new(&mut this.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {}
+            r => return r,
+        }
+        let new = &buf.filled()[before..];
+        let r = this.state.check(new.len(), new);
+        let n = match r {
+            Ok(n) => n,
+            Err(e) => {
+                buf.set_filled(before);
+                return Poll::Ready(Err(e));
+            }
+        };
+        buf.set_filled(before + n);
+        this.state.consume(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A stream of `Bytes` that validates the chunks of `S`.
+///
+/// Yields the chunks of `S` as they are validated. The valid bytes of a
+/// chunk that precede an error are yielded first, followed by the error,
+/// whose offsets are relative to the start of the stream, and the end of the
+/// stream. As with `Utf8Reader::fill_buf`, the leading bytes of a character split
+/// across chunks are yielded before the rest of the character arrives.
+#[derive(Debug)]
+pub struct Utf8Stream<S> {
+    inner: S,
+    validator: Utf8Validator,
+    /// Number of bytes yielded.
+    pos: usize,
+    error: Option<Error>,
+    done: bool,
+}
+
+impl<S> Utf8Stream<S> {
+    /// Creates a stream that validates the chunks of `inner`.
+    pub fn new(inner: S) -> Self {
+        Utf8Stream {
+            inner,
+            validator: Utf8Validator::new(),
+            pos: 0,
+            error: None,
+            done: false,
+        }
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Stream<Item = Bytes> + Unpin> Stream for Utf8Stream<S> {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        if let Some(e) = this.error {
+            this.done = true;
+            return Poll::Ready(Some(Err(e)));
+        }
+        let r = match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Some(chunk)) => match this.validator.feed(&chunk) {
+                Ok(()) => Ok(chunk),
+                Err(e) => Err((chunk, e)),
+            },
+            Poll::Ready(None) => {
+                this.done = true;
+                match this.validator.finish() {
+                    Ok(()) => return Poll::Ready(None),
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                }
+            }
+        };
+        match r {
+            Ok(chunk) => {
+                this.pos += chunk.len();
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Err((chunk, e)) => {
+                this.error = Some(e);
+                match e.valid_up_to().saturating_sub(this.pos) {
+                    0 => {
+                        this.done = true;
+                        Poll::Ready(Some(Err(e)))
+                    }
+                    valid => {
+                        this.pos += valid;
+                        Poll::Ready(Some(Ok(chunk.slice(..valid))))
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+    use std::task::Waker;
+
+    const INPUTS: &[&[u8]] = &[
+        b"",
+        b"a\xE2\x82\xAC\xF0\x9F\x98\x80\xCE\xBA",
+        b"ab\xF0\x9F\x98",
+        b"\xE2\x82\xACd\xF0\x9F\x98\x41",
+        b"ab\xED\xA0\x80",
+    ];
+
+    /// Splits `x` at `i` and `j`.
+    fn split(x: &[u8], i: usize, j: usize) -> Vec<&[u8]> {
+        vec![&x[..i], &x[i..j], &x[j..]]
+    }
+
+    /// Returns the concatenated output, and the error if any.
+    fn collect<T, F>(mut poll: F) -> (Vec<u8>, Option<Error>)
+    where
+        F: FnMut(&mut Context<'_>) -> Poll<Option<Result<T, Error>>>,
+        T: AsRef<[u8]>,
+    {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut out = Vec::new();
+        loop {
+            match poll(&mut cx) {
+                Poll::Pending => {}
+                Poll::Ready(None) => return (out, None),
+                Poll::Ready(Some(Ok(x))) => out.extend_from_slice(x.as_ref()),
+                Poll::Ready(Some(Err(e))) => return (out, Some(e)),
+            }
+        }
+    }
+
+    fn check(x: &[u8], out: &[u8], error: Option<Error>) {
+        match ::is_utf8(x) {
+            Ok(()) => {
+                assert_eq!(error, None);
+                assert_eq!(out, x);
+            }
+            Err(e) => {
+                assert_eq!(error, Some(e));
+                assert!(out.len() >= e.valid_up_to());
+                assert_eq!(out, &x[..out.len()]);
+            }
+        }
+    }
+
+    /// Hands out one chunk per poll, after a pending poll.
+    struct Chunks<'a> {
+        chunks: Vec<&'a [u8]>,
+        pending: bool,
+    }
+
+    impl<'a> AsyncRead for Chunks<'a> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            self.pending = !self.pending;
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            if !self.chunks.is_empty() {
+                let chunk = self.chunks.remove(0);
+                buf.put_slice(chunk);
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_async_read() {
+        for x in INPUTS {
+            for i in 0..=x.len() {
+                for j in i..=x.len() {
+                    let chunks = Chunks {
+                        // An empty chunk ends the stream.
+                        chunks: split(x, i, j)
+                            .into_iter()
+                            .filter(|c| !c.is_empty())
+                            .collect(),
+                        pending: false,
+                    };
+                    let mut r = AsyncUtf8Reader::new(chunks);
+                    let (out, error) = collect(|cx| {
+                        let mut b = [0; 16];
+                        let mut buf = ReadBuf::new(&mut b);
+                        match Pin::new(&mut r).poll_read(cx, &mut buf) {
+                            Poll::Pending => Poll::Pending,
+                            Poll::Ready(Ok(())) if buf.filled().is_empty() => {
+                                Poll::Ready(None)
+                            }
+                            Poll::Ready(Ok(())) => {
+                                Poll::Ready(Some(Ok(buf.filled().to_vec())))
+                            }
+                            Poll::Ready(Err(e)) => {
+                                let e = e.into_inner().unwrap();
+                                let e = *e.downcast::<Error>().unwrap();
+                                Poll::Ready(Some(Err(e)))
+                            }
+                        }
+                    });
+                    check(x, &out, error);
+                }
+            }
+        }
+    }
+
+    struct ChunkStream(Vec<Bytes>);
+
+    impl Stream for ChunkStream {
+        type Item = Bytes;
+
+        fn poll_next(
+            mut self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+        ) -> Poll<Option<Bytes>> {
+            if self.0.is_empty() {
+                Poll::Ready(None)
+            } else {
+                Poll::Ready(Some(self.0.remove(0)))
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream() {
+        for x in INPUTS {
+            for i in 0..=x.len() {
+                for j in i..=x.len() {
+                    let chunks = split(x, i, j)
+                        .into_iter()
+                        .map(Bytes::copy_from_slice)
+                        .collect();
+                    let mut s = Utf8Stream::new(ChunkStream(chunks));
+                    let (out, error) =
+                        collect(|cx| Pin::new(&mut s).poll_next(cx));
+                    check(x, &out, error);
+                    // The stream is fused.
+                    let mut cx = Context::from_waker(Waker::noop());
+                    let end = Pin::new(&mut s).poll_next(&mut cx);
+                    assert!(matches!(end, Poll::Ready(None)));
+                }
+            }
+        }
+    }
+}
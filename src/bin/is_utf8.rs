@@ -0,0 +1,120 @@
+//! Checks whether files are valid UTF-8.
+//!
+//! Usage: `is_utf8 [FILE]...`, where no files or `-` read the standard
+//! input. Prints `file:line:column: byte offset N: <error kind>` for every
+//! invalid file, where line and column are 1-based and the column counts
+//! characters. Exits with 1 if a file is invalid, and with 2 on I/O errors.
+
+extern crate is_utf8;
+
+use is_utf8::{Error, Location, Utf8Reader};
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process;
+
+/// Returns the location of the byte at `offset` of `r`.
+fn locate<R: Read>(r: R, offset: u64) -> io::Result<Location> {
+    let mut r = BufReader::new(r.take(offset));
+    let mut location = Location::new();
+    loop {
+        let n = {
+            let buf = r.fill_buf()?;
+            location.advance(buf);
+            buf.len()
+        };
+        if n == 0 {
+            return Ok(location);
+        }
+        r.consume(n);
+    }
+}
+
+/// Validates the bytes read from `r`, returning the error and its line and
+/// column if they are invalid.
+///
+/// The input is streamed, so it may be larger than the available memory.
+fn check_reader<R: Read>(r: R) -> io::Result<Option<(Error, Location)>> {
+    let mut r = Utf8Reader::new(r);
+    let mut location = Location::new();
+    let mut buf = vec![0; 1 << 16];
+    loop {
+        // The reader hands out exactly the valid bytes before an error.
+        let e = match r.read(&mut buf) {
+            Ok(0) => return Ok(None),
+            Ok(n) => {
+                location.advance(&buf[..n]);
+                continue;
+            }
+            Err(e) => e,
+        };
+        if e.kind() == io::ErrorKind::Interrupted {
+            continue;
+        }
+        match e.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            Some(&error) => return Ok(Some((error, location))),
+            None => return Err(e),
+        }
+    }
+}
+
+/// Validates the file `name`, returning the error and its line and column
+/// if it is invalid.
+fn check_file(name: &str) -> io::Result<Option<(Error, Location)>> {
+    let file = File::open(name)?;
+    // Pipes and the like cannot be read twice.
+    if !file.metadata()?.is_file() {
+        return check_reader(file);
+    }
+    match is_utf8::validate_open_file(&file)? {
+        Ok(()) => Ok(None),
+        Err(e) => Ok(Some((e, locate(file, e.valid_up_to() as u64)?))),
+    }
+}
+
+/// Validates the file `name`, returning whether it is valid.
+fn check(name: &str) -> io::Result<bool> {
+    let r = if name == "-" {
+        check_reader(io::stdin())?
+    } else {
+        check_file(name)?
+    };
+    let (e, location) = match r {
+        Some(r) => r,
+        None => return Ok(true),
+    };
+    let name = if name == "-" {
+        "(standard input)"
+    } else {
+        name
+    };
+    let stdout = io::stdout();
+    writeln!(
+        stdout.lock(),
+        "{}:{}: byte offset {}: {}",
+        name,
+        location,
+        e.valid_up_to(),
+        e.kind()
+    )?;
+    Ok(false)
+}
+
+fn main() {
+    let mut names: Vec<String> = env::args().skip(1).collect();
+    if names.is_empty() {
+        names.push("-".to_string());
+    }
+    let mut code = 0;
+    for name in &names {
+        match check(name) {
+            Ok(true) => {}
+            Ok(false) => code = code.max(1),
+            Err(e) => {
+                eprintln!("is_utf8: {}: {}", name, e);
+                code = 2;
+            }
+        }
+    }
+    process::exit(code);
+}
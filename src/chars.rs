@@ -0,0 +1,125 @@
+//! Decoding the characters of the input.
+
+use core::iter::FusedIterator;
+use core::{char, cmp};
+use hoehrmann::{decode_codep, error_kind, UTF8_ACCEPT, UTF8_REJECT};
+use {Error, ErrorKind};
+
+/// Iterator over the characters of a byte slice, created by `chars`.
+#[derive(Clone, Debug)]
+pub struct Chars<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+/// Returns an iterator that decodes and validates the characters of `x` in
+/// one pass with the Höhrmann DFA.
+///
+/// Each maximal invalid subsequence is yielded as an `Error`, after which
+/// decoding resumes, as for `errors`. The offsets of the errors are relative
+/// to the start of `x`.
+pub fn chars<'a>(x: &'a [u8]) -> Chars<'a> {
+    Chars { input: x, pos: 0 }
+}
+
+impl<'a> Chars<'a> {
+    /// Returns the offset of the next character in the input.
+    pub fn offset(&self) -> usize {
+        self.pos
+    }
+}
+
+impl<'a> Iterator for Chars<'a> {
+    type Item = Result<char, Error>;
+
+    fn next(&mut self) -> Option<Result<char, Error>> {
+        let x = self.input;
+        let start = self.pos;
+        match x.get(start) {
+            None => return None,
+            Some(&b) if b < 0x80 => {
+                self.pos += 1;
+                return Some(Ok(b as char));
+            }
+            Some(_) => {}
+        }
+
+        let mut state = UTF8_ACCEPT;
+        let mut codep = 0;
+        for (i, &byte) in x[start..].iter().enumerate() {
+            let prev = state;
+            state = unsafe { decode_codep(state, &mut codep, byte) };
+            match state {
+                UTF8_ACCEPT => {
+                    self.pos = start + i + 1;
+                    return Some(Ok(unsafe {
+                        char::from_u32_unchecked(codep)
+                    }));
+                }
+                UTF8_REJECT => {
+                    let error_len = cmp::max(1, i);
+                    self.pos = start + error_len;
+                    let kind = error_kind(prev, byte);
+                    return Some(Err(Error::new(
+                        start,
+                        Some(error_len as u8),
+                        kind,
+                    )));
+                }
+                _ => {}
+            }
+        }
+        self.pos = x.len();
+        Some(Err(Error::new(start, None, ErrorKind::Truncated)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.input.len() - self.pos;
+        (len.div_ceil(4), Some(len))
+    }
+}
+
+impl<'a> FusedIterator for Chars<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    const INPUTS: &[&[u8]] = &[
+        b"",
+        b"abc",
+        b"\xCE\xBA\xE1\xBD\xB9\xCF\x83\xCE\xBC\xCE\xB5 \xF0\x90\x8D\x88",
+        b"\xF4\x8F\xBF\xBF\xEF\xBF\xBF\xC2\x80\xDF\xBF",
+        b"\x80",
+        b"\xF0\x90\x80",
+        b"a\xC0\x80b\xED\xA0\x80c\xF4\x90\x80\x80d\xE1\x80",
+        b"\xF0\x90\x80\x41\xF0\x90\x80\xF0\x90\x80\x80",
+        b"\xFF\xFE abc \xC2",
+    ];
+
+    #[test]
+    fn test_chars() {
+        for x in INPUTS {
+            let decoded: String = chars(x)
+                .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect();
+            assert_eq!(decoded, String::from_utf8_lossy(x), "{:x?}", x);
+            let errors: Vec<_> = chars(x).filter_map(|r| r.err()).collect();
+            let expected: Vec<_> = ::errors(x).collect();
+            assert_eq!(errors, expected, "{:x?}", x);
+        }
+    }
+
+    #[test]
+    fn test_offset() {
+        let x = "aκ\u{10348}".as_bytes();
+        let mut it = chars(x);
+        let mut offsets = vec![it.offset()];
+        while it.next().is_some() {
+            offsets.push(it.offset());
+        }
+        assert_eq!(offsets, [0, 1, 3, 7]);
+        assert_eq!(it.next(), None);
+    }
+}
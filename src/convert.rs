@@ -0,0 +1,72 @@
+//! Conversions from bytes to strings.
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::str;
+use Error;
+
+/// Converts a slice of bytes to a string slice.
+///
+/// Like `core::str::from_utf8`, but validated with `is_utf8`.
+pub fn from_utf8(x: &[u8]) -> Result<&str, Error> {
+    ::is_utf8(x)?;
+    Ok(unsafe { str::from_utf8_unchecked(x) })
+}
+
+/// Converts a mutable slice of bytes to a mutable string slice.
+///
+/// Like `core::str::from_utf8_mut`, but validated with `is_utf8`.
+pub fn from_utf8_mut(x: &mut [u8]) -> Result<&mut str, Error> {
+    ::is_utf8(x)?;
+    Ok(unsafe { str::from_utf8_unchecked_mut(x) })
+}
+
+/// Converts a vector of bytes to a `String` without copying it.
+///
+/// On error the vector is handed back together with the error.
+#[cfg(feature = "alloc")]
+pub fn from_utf8_vec(x: Vec<u8>) -> Result<String, (Vec<u8>, Error)> {
+    match ::is_utf8(&x) {
+        Ok(()) => Ok(unsafe { String::from_utf8_unchecked(x) }),
+        Err(e) => Err((x, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    #[test]
+    fn test_from_utf8() {
+        assert_eq!(from_utf8("κόσμε".as_bytes()), Ok("κόσμε"));
+        let e = from_utf8(b"ab\xC0\x80").unwrap_err();
+        assert_eq!(e.valid_up_to(), 2);
+    }
+
+    #[test]
+    fn test_from_utf8_mut() {
+        let mut x = *b"hello";
+        from_utf8_mut(&mut x).unwrap().make_ascii_uppercase();
+        assert_eq!(&x, b"HELLO");
+        let mut x = *b"\xFFhello";
+        assert_eq!(from_utf8_mut(&mut x).unwrap_err().valid_up_to(), 0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_from_utf8_vec() {
+        let v = "κόσμε".as_bytes().to_vec();
+        let ptr = v.as_ptr();
+        let s = from_utf8_vec(v).unwrap();
+        assert_eq!(s, "κόσμε");
+        assert_eq!(s.as_ptr(), ptr);
+
+        let v = b"ab\xE2\x82".to_vec();
+        let (v, e) = from_utf8_vec(v).unwrap_err();
+        assert_eq!(v, b"ab\xE2\x82");
+        assert_eq!((e.valid_up_to(), e.error_len()), (2, None));
+    }
+}
@@ -0,0 +1,275 @@
+//! Validation errors.
+
+use core::{cmp, fmt};
+use core::str::{self, Utf8Error};
+
+/// The reason a byte sequence is not valid UTF-8.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// A continuation byte (`0x80..=0xBF`) where a lead byte was expected.
+    UnexpectedContinuation,
+    /// A code point encoded with more bytes than necessary, e.g. `C0 80`.
+    Overlong,
+    /// An encoded surrogate code point (`U+D800..=U+DFFF`), or in encodings
+    /// that allow surrogates, one that is not paired as the encoding
+    /// requires.
+    Surrogate,
+    /// A code point above `U+10FFFF` (`F4 90..=BF`).
+    OutOfRange,
+    /// A lead byte `F5..=FF`, which could only start a code point above
+    /// `U+10FFFF` or a 5- or 6-byte sequence of the original UTF-8.
+    InvalidLeadByte,
+    /// A multi-byte sequence that ends before all of its continuation bytes.
+    Truncated,
+    /// A well-formed 4-byte sequence in an encoding that represents
+    /// supplementary characters (`U+10000..=U+10FFFF`) differently, e.g. as
+    /// a surrogate pair, or not at all, e.g. MySQL's `utf8mb3`. The error
+    /// spans the 4 bytes of the sequence.
+    Supplementary,
+    /// A `00` byte in an encoding that represents `U+0000` differently,
+    /// e.g. as `C0 80`.
+    Nul,
+    /// A well-formed character that the validation policy disallows.
+    Disallowed(char),
+}
+
+impl ErrorKind {
+    /// Returns `true` if the sequence is well-formed UTF-8 that the encoding
+    /// or policy rejects.
+    fn is_well_formed(self) -> bool {
+        matches!(
+            self,
+            ErrorKind::Supplementary
+                | ErrorKind::Nul
+                | ErrorKind::Disallowed(_)
+        )
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let ErrorKind::Disallowed(c) = *self {
+            return write!(f, "disallowed character U+{:04X}", c as u32);
+        }
+        f.write_str(match *self {
+            ErrorKind::UnexpectedContinuation => {
+                "unexpected continuation byte"
+            }
+            ErrorKind::Overlong => "overlong encoding",
+            ErrorKind::Surrogate => "encoded surrogate",
+            ErrorKind::OutOfRange => "code point out of range",
+            ErrorKind::InvalidLeadByte => "invalid lead byte",
+            ErrorKind::Truncated => "truncated sequence",
+            ErrorKind::Supplementary => "supplementary character",
+            ErrorKind::Nul => "NUL byte",
+            ErrorKind::Disallowed(_) => unreachable!(),
+        })
+    }
+}
+
+/// Errors which can occur when attempting to interpret a sequence of `u8`
+/// as a string.
+///
+/// `valid_up_to` and `error_len` have the same meaning, and the errors of
+/// `is_utf8` the same `Display` output, as `core::str::Utf8Error`. On top of
+/// that the error reports why the sequence is invalid.
+///
+/// The validators of encodings derived from UTF-8 and of policies report
+/// errors of the same structure, for sequences that may be valid UTF-8.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Error {
+    valid_up_to: usize,
+    error_len: Option<u8>,
+    kind: ErrorKind,
+}
+
+impl Error {
+    pub(crate) fn new(
+        valid_up_to: usize,
+        error_len: Option<u8>,
+        kind: ErrorKind,
+    ) -> Self {
+        Error {
+            valid_up_to,
+            error_len,
+            kind,
+        }
+    }
+
+    /// Returns the same error for input that starts `n` bytes earlier.
+    pub(crate) fn offset(self, n: usize) -> Self {
+        Error {
+            valid_up_to: self.valid_up_to + n,
+            ..self
+        }
+    }
+
+    /// Returns the index in the given string up to which valid UTF-8 was
+    /// verified.
+    ///
+    /// It is the maximum index such that the validator that reported the
+    /// error, e.g. `is_utf8`, would return `Ok(_)` for `&input[..index]`.
+    pub fn valid_up_to(&self) -> usize {
+        self.valid_up_to
+    }
+
+    /// Provides more information about the failure:
+    ///
+    /// * `None`: the end of the input was reached unexpectedly.
+    ///   `self.valid_up_to()` is 1 to 3 bytes from the end of the input,
+    ///   or up to 5 for a truncated 5- or 6-byte sequence of
+    ///   `Utf8Flavor::Rfc2279` or a truncated surrogate pair of the flavors
+    ///   that pair surrogates. If a byte stream (such as a file or a network
+    ///   socket) is being decoded incrementally, this could be a valid `char`
+    ///   whose byte sequence is spanning multiple chunks.
+    ///
+    /// * `Some(len)`: an unexpected byte was encountered. The length
+    ///   provided is that of the invalid byte sequence that starts at the
+    ///   index given by `valid_up_to()`: 1 to 3 bytes for malformed UTF-8,
+    ///   the whole character for a well-formed one that is rejected, e.g. 4
+    ///   bytes for `ErrorKind::Supplementary`, and up to 5 bytes for
+    ///   `Utf8Flavor::Rfc2279`. Decoding should resume after that
+    ///   sequence (after inserting a `U+FFFD REPLACEMENT CHARACTER`) in
+    ///   case of lossy decoding.
+    pub fn error_len(&self) -> Option<usize> {
+        self.error_len.map(|len| len as usize)
+    }
+
+    /// Returns why the byte sequence is invalid.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the `Utf8Error` that `core::str::from_utf8` reports for the
+    /// `input` this error was reported for.
+    ///
+    /// `Utf8Error` cannot be constructed outside of `core`, so this
+    /// revalidates `input` up to the error. Returns `None` if
+    /// `core::str::from_utf8` does not report this error for `input`: if
+    /// the error was reported for another input, or by a validator other
+    /// than `is_utf8`, e.g. for a character a policy disallows.
+    pub fn to_utf8_error(&self, input: &[u8]) -> Option<Utf8Error> {
+        // Include the byte that ended the invalid sequence, if any.
+        let end = match self.error_len() {
+            Some(len) => self.valid_up_to.saturating_add(len + 1),
+            None => input.len(),
+        };
+        match str::from_utf8(&input[..cmp::min(end, input.len())]) {
+            Err(e) if *self == e => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq<Utf8Error> for Error {
+    fn eq(&self, other: &Utf8Error) -> bool {
+        self.valid_up_to() == other.valid_up_to()
+            && self.error_len() == other.error_len()
+    }
+}
+
+impl PartialEq<Error> for Utf8Error {
+    fn eq(&self, other: &Error) -> bool {
+        other == self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.kind.is_well_formed() {
+            write!(f, "{} at index {}", self.kind, self.valid_up_to)
+        } else if let Some(error_len) = self.error_len {
+            write!(
+                f,
+                "invalid utf-8 sequence of {} bytes from index {}",
+                error_len, self.valid_up_to
+            )
+        } else {
+            write!(
+                f,
+                "incomplete utf-8 byte sequence from index {}",
+                self.valid_up_to
+            )
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    const INVALID: &[&[u8]] = &[
+        b"ab\x80cd",
+        b"ab\xC0\x80",
+        b"ab\xE0\x80\x80",
+        b"\xED\xA0\x80",
+        b"\xF4\x90\x80\x80",
+        b"\xF0\x90\x80\x41",
+        b"\xF0\x90\x80",
+        b"\xFF",
+    ];
+
+    #[test]
+    fn test_matches_utf8_error() {
+        for x in INVALID {
+            let e = ::is_utf8(x).unwrap_err();
+            let std = str::from_utf8(x).unwrap_err();
+            assert_eq!(e, std);
+            assert_eq!(std, e);
+            assert_eq!(e.to_string(), std.to_string());
+            assert_eq!(e.to_utf8_error(x), Some(std));
+        }
+    }
+
+    #[test]
+    fn test_well_formed() {
+        let inputs: [&[u8]; 3] = [b"a\x01", b"\xF0\x90\x8D\x88", b"a\x00"];
+        let errors = [
+            ::is_utf8_policy(inputs[0], ::Policy::C0_CONTROLS),
+            ::is_utf8_mb3(inputs[1]),
+            ::is_utf8_flavor(inputs[2], ::Utf8Flavor::ModifiedUtf8),
+        ];
+        let messages = [
+            "disallowed character U+0001 at index 1",
+            "supplementary character at index 0",
+            "NUL byte at index 1",
+        ];
+        for i in 0..3 {
+            let (x, e) = (inputs[i], errors[i].unwrap_err());
+            let message = messages[i];
+            assert_eq!(e.to_string(), message);
+            assert_eq!(e.to_utf8_error(x), None);
+        }
+        // A high surrogate at the end of CESU-8 is truncated, while
+        // `from_utf8` rejects its second byte.
+        let x = b"a\xED\xA0\x81";
+        let e = ::is_utf8_flavor(x, ::Utf8Flavor::Cesu8).unwrap_err();
+        assert_eq!(e.to_utf8_error(x), None);
+        // Not the input the error was reported for.
+        let e = ::is_utf8(b"ab\xFF").unwrap_err();
+        assert_eq!(e.to_utf8_error(b"a"), None);
+        assert_eq!(e.to_utf8_error(b"abc\xFF"), None);
+    }
+
+    #[test]
+    fn test_kind() {
+        use self::ErrorKind::*;
+        let kinds = [
+            UnexpectedContinuation,
+            Overlong,
+            Overlong,
+            Surrogate,
+            OutOfRange,
+            Truncated,
+            Truncated,
+            InvalidLeadByte,
+        ];
+        for (x, &kind) in INVALID.iter().zip(kinds.iter()) {
+            assert_eq!(::is_utf8(x).unwrap_err().kind(), kind, "{:x?}", x);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Iterating over every error of the input.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::iter::FusedIterator;
+use Error;
+
+/// Iterator over the errors of a byte slice, created by `errors`.
+#[derive(Clone, Debug)]
+pub struct Errors<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+/// Returns an iterator over every error of `x`.
+///
+/// Validation resumes after the maximal invalid subsequence reported by
+/// `Error::error_len`, that is, the errors are those that a lossy
+/// conversion replaces with `U+FFFD`. The offsets of the errors are
+/// relative to the start of `x`.
+pub fn errors<'a>(x: &'a [u8]) -> Errors<'a> {
+    Errors { input: x, pos: 0 }
+}
+
+/// Returns every error of `x`, or the first `limit` ones.
+#[cfg(feature = "alloc")]
+pub fn collect_errors(x: &[u8], limit: Option<usize>) -> Vec<Error> {
+    match limit {
+        Some(limit) => errors(x).take(limit).collect(),
+        None => errors(x).collect(),
+    }
+}
+
+impl<'a> Iterator for Errors<'a> {
+    type Item = Error;
+
+    fn next(&mut self) -> Option<Error> {
+        let len = self.input.len();
+        if self.pos >= len {
+            return None;
+        }
+        match ::is_utf8(&self.input[self.pos..]) {
+            Ok(()) => {
+                self.pos = len;
+                None
+            }
+            Err(e) => {
+                let e = e.offset(self.pos);
+                self.pos = match e.error_len() {
+                    Some(error_len) => e.valid_up_to() + error_len,
+                    None => len,
+                };
+                Some(e)
+            }
+        }
+    }
+}
+
+impl<'a> FusedIterator for Errors<'a> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+    use core::str;
+
+    /// The errors `core::str::from_utf8` reports when resuming after each.
+    fn std_errors(x: &[u8]) -> Vec<(usize, Option<usize>)> {
+        let mut r = Vec::new();
+        let mut pos = 0;
+        while let Err(e) = str::from_utf8(&x[pos..]) {
+            r.push((pos + e.valid_up_to(), e.error_len()));
+            match e.error_len() {
+                Some(len) => pos += e.valid_up_to() + len,
+                None => break,
+            }
+        }
+        r
+    }
+
+    #[test]
+    fn test_errors() {
+        let inputs: &[&[u8]] = &[
+            b"",
+            b"valid \xE2\x82\xAC",
+            b"\x80\x80\x80",
+            b"a\xC0\x80b\xED\xA0\x80c\xF4\x90\x80\x80d\xE1\x80",
+            b"\xF0\x90\x80\x41\xF0\x90\x80\xF0\x90\x80\x80",
+            b"\xFF\xFE abcdefghijklmnopqrstuvwxyz0123456789 \xC2",
+        ];
+        for x in inputs {
+            let errors: Vec<_> = errors(x)
+                .map(|e| (e.valid_up_to(), e.error_len()))
+                .collect();
+            assert_eq!(errors, std_errors(x), "{:x?}", x);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_collect_errors() {
+        use ErrorKind;
+        let x = b"a\x80b\xC0\x80c\xED\xA0\x80d\xF0\x90";
+        let kinds: Vec<_> =
+            collect_errors(x, None).iter().map(|e| e.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                ErrorKind::UnexpectedContinuation,
+                ErrorKind::Overlong,
+                ErrorKind::UnexpectedContinuation,
+                ErrorKind::Surrogate,
+                ErrorKind::UnexpectedContinuation,
+                ErrorKind::UnexpectedContinuation,
+                ErrorKind::Truncated,
+            ]
+        );
+        assert_eq!(collect_errors(x, Some(2)).len(), 2);
+        assert_eq!(collect_errors(x, Some(0)).len(), 0);
+        assert_eq!(collect_errors(b"valid", None).len(), 0);
+    }
+}
@@ -0,0 +1,78 @@
+//! Validation of files.
+
+use memmap2::Mmap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use {Error, Utf8Reader};
+
+/// Checks whether the file at `path` is valid UTF-8.
+///
+/// Regular files are memory-mapped and validated with `par_is_utf8`, so
+/// they may be larger than the available memory. Other files, like pipes,
+/// are read through a `Utf8Reader`.
+///
+/// Returns an I/O error if the file cannot be read. The file must not be
+/// truncated while it is validated, which may crash the process on some
+/// platforms.
+pub fn validate_file<P: AsRef<Path>>(
+    path: P,
+) -> io::Result<Result<(), Error>> {
+    validate_open_file(&File::open(path)?)
+}
+
+/// Checks whether the open `file` is valid UTF-8, like `validate_file`.
+///
+/// Regular files are validated from the start, whatever the position of
+/// `file`, which is left unchanged. Other files are read from `file`.
+pub fn validate_open_file(file: &File) -> io::Result<Result<(), Error>> {
+    let metadata = file.metadata()?;
+    if !metadata.is_file() {
+        return validate_reader(file);
+    }
+    if metadata.len() == 0 {
+        return Ok(Ok(()));
+    }
+    let map = unsafe { Mmap::map(file)? };
+    Ok(::par_is_utf8(&map))
+}
+
+/// Checks whether the bytes read from `r` are valid UTF-8.
+fn validate_reader<R: io::Read>(r: R) -> io::Result<Result<(), Error>> {
+    match io::copy(&mut Utf8Reader::new(r), &mut io::sink()) {
+        Ok(_) => Ok(Ok(())),
+        Err(e) => match e.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+            Some(&e) => Ok(Err(e)),
+            None => Err(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::prelude::v1::*;
+    use std::process;
+
+    #[test]
+    fn test_validate_file() {
+        let dir = env::temp_dir();
+        let inputs: &[&[u8]] =
+            &[b"", "κόσμε\n".as_bytes(), b"ab\ncd\xE2\x82", b"\xFF"];
+        for (i, x) in inputs.iter().enumerate() {
+            let path =
+                dir.join(format!("is_utf8_{}_{}.txt", process::id(), i));
+            fs::write(&path, x).unwrap();
+            let r = validate_file(&path).unwrap();
+            let file = File::open(&path).unwrap();
+            let r_open = validate_open_file(&file).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(r, ::is_utf8(x));
+            assert_eq!(r_open, ::is_utf8(x));
+            assert_eq!(validate_reader(*x).unwrap(), ::is_utf8(x));
+        }
+        assert!(validate_file(dir.join("is_utf8_missing.txt")).is_err());
+    }
+}
@@ -0,0 +1,405 @@
+//! Validation of encodings derived from UTF-8.
+
+use rustc::utf8_char_width;
+use {Error, ErrorKind};
+
+/// An encoding derived from UTF-8.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Utf8Flavor {
+    /// UTF-8 as specified by RFC 3629, as validated by `is_utf8`.
+    Strict,
+    /// CESU-8: supplementary characters are encoded as a surrogate pair of
+    /// 3-byte sequences instead of as a 4-byte sequence.
+    Cesu8,
+    /// Java's Modified UTF-8: CESU-8 in which `U+0000` is encoded as
+    /// `C0 80` instead of as `00`.
+    ModifiedUtf8,
+    /// WTF-8: UTF-8 in which surrogates are valid, unless a high surrogate
+    /// is followed by a low one, since the pair must be encoded as a 4-byte
+    /// sequence.
+    Wtf8,
+    /// UTF-8 as originally specified by RFC 2279, which encodes 31-bit
+    /// values in up to 6 bytes, including surrogates and values above
+    /// `U+10FFFF`. Overlong encodings are still invalid.
+    Rfc2279,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Surrogate {
+    High,
+    Low,
+}
+
+/// Validates the sequence at the start of `v`, returning its length and
+/// whether it encodes a surrogate, or the length and kind of the error.
+#[inline]
+fn sequence(
+    v: &[u8],
+    flavor: Utf8Flavor,
+) -> Result<(usize, Option<Surrogate>), (Option<u8>, ErrorKind)> {
+    macro_rules! byte {
+        ($i:expr) => {
+            match v.get($i) {
+                Some(&b) => b,
+                None => return Err((None, ErrorKind::Truncated)),
+            }
+        };
+    }
+    macro_rules! tail {
+        ($i:expr) => {
+            if byte!($i) & 0xC0 != 0x80 {
+                return Err((Some($i), ErrorKind::Truncated));
+            }
+        };
+    }
+
+    let first = v[0];
+    match (first, flavor) {
+        (0x00, Utf8Flavor::ModifiedUtf8) => {
+            return Err((Some(1), ErrorKind::Nul));
+        }
+        (0x00..=0x7F, _) => return Ok((1, None)),
+        (0xC0, Utf8Flavor::ModifiedUtf8) => {
+            return match byte!(1) {
+                0x80 => Ok((2, None)),
+                _ => Err((Some(1), ErrorKind::Overlong)),
+            };
+        }
+        _ => {}
+    }
+    match utf8_char_width(first) {
+        2 => {
+            tail!(1);
+            Ok((2, None))
+        }
+        3 => {
+            let surrogate = match (first, byte!(1)) {
+                (0xE0, 0xA0..=0xBF)
+                | (0xE1..=0xEC, 0x80..=0xBF)
+                | (0xED, 0x80..=0x9F)
+                | (0xEE..=0xEF, 0x80..=0xBF) => None,
+                (0xED, 0xA0..=0xAF) => Some(Surrogate::High),
+                (0xED, 0xB0..=0xBF) => Some(Surrogate::Low),
+                (0xE0, 0x80..=0x9F) => {
+                    return Err((Some(1), ErrorKind::Overlong));
+                }
+                _ => return Err((Some(1), ErrorKind::Truncated)),
+            };
+            tail!(2);
+            Ok((3, surrogate))
+        }
+        4 => {
+            match (first, byte!(1)) {
+                (0xF0, 0x90..=0xBF)
+                | (0xF1..=0xF3, 0x80..=0xBF)
+                | (0xF4, 0x80..=0x8F) => {}
+                (0xF0, 0x80..=0x8F) => {
+                    return Err((Some(1), ErrorKind::Overlong));
+                }
+                (0xF4, 0x90..=0xBF) => {
+                    return Err((Some(1), ErrorKind::OutOfRange));
+                }
+                _ => return Err((Some(1), ErrorKind::Truncated)),
+            }
+            tail!(2);
+            tail!(3);
+            match flavor {
+                Utf8Flavor::Wtf8 => Ok((4, None)),
+                _ => Err((Some(4), ErrorKind::Supplementary)),
+            }
+        }
+        _ => Err((
+            Some(1),
+            match first {
+                0x80..=0xBF => ErrorKind::UnexpectedContinuation,
+                0xC0 | 0xC1 => ErrorKind::Overlong,
+                _ => ErrorKind::InvalidLeadByte,
+            },
+        )),
+    }
+}
+
+/// Validates the sequence at the start of `v` as RFC 2279 specifies.
+#[inline]
+fn sequence_rfc2279(
+    v: &[u8],
+) -> Result<(usize, Option<Surrogate>), (Option<u8>, ErrorKind)> {
+    let first = v[0];
+    // The width, and the minimum second byte of a sequence that is not
+    // overlong.
+    let (w, min) = match first {
+        0x00..=0x7F => return Ok((1, None)),
+        0x80..=0xBF => {
+            return Err((Some(1), ErrorKind::UnexpectedContinuation));
+        }
+        0xC0 | 0xC1 => return Err((Some(1), ErrorKind::Overlong)),
+        0xC2..=0xDF => (2, 0x80),
+        0xE0 => (3, 0xA0),
+        0xE1..=0xEF => (3, 0x80),
+        0xF0 => (4, 0x90),
+        0xF1..=0xF7 => (4, 0x80),
+        0xF8 => (5, 0x88),
+        0xF9..=0xFB => (5, 0x80),
+        0xFC => (6, 0x84),
+        0xFD => (6, 0x80),
+        0xFE | 0xFF => return Err((Some(1), ErrorKind::InvalidLeadByte)),
+    };
+    for i in 1..w {
+        let b = match v.get(i) {
+            Some(&b) => b,
+            None => return Err((None, ErrorKind::Truncated)),
+        };
+        if b & 0xC0 != 0x80 {
+            return Err((Some(i as u8), ErrorKind::Truncated));
+        }
+        if i == 1 && b < min {
+            return Err((Some(1), ErrorKind::Overlong));
+        }
+    }
+    Ok((w, None))
+}
+
+/// Returns `true` if the incomplete sequence `v` may be a low surrogate.
+fn low_surrogate(v: &[u8]) -> bool {
+    v[0] == 0xED && v.get(1).is_none_or(|b| (0xB0..=0xBF).contains(b))
+}
+
+/// Returns `Ok` if `x` is valid in the encoding `flavor`.
+///
+/// Errors have the same structure as those of `is_utf8`. A surrogate that is
+/// not paired as `flavor` requires is an error of 3 bytes of kind
+/// `Surrogate`. A high surrogate at the end of a CESU-8 input, or followed by
+/// a truncated sequence that may be a low surrogate, is truncated.
+pub fn is_utf8_flavor(x: &[u8], flavor: Utf8Flavor) -> Result<(), Error> {
+    if flavor == Utf8Flavor::Strict {
+        return ::is_utf8(x);
+    }
+    // Whether supplementary characters are encoded as surrogate pairs.
+    let pairs =
+        flavor == Utf8Flavor::Cesu8 || flavor == Utf8Flavor::ModifiedUtf8;
+    let mut index = 0;
+    // The offset of the high surrogate the previous sequence encodes.
+    let mut high = None;
+    while index < x.len() {
+        let ascii = x[index] < 0x80 && flavor != Utf8Flavor::ModifiedUtf8;
+        if ascii && high.is_none() {
+            // Ascii case, skip forward quickly.
+            match ::runtime::is_ascii(&x[index..]) {
+                Ok(()) => return Ok(()),
+                Err(n) => {
+                    index += n;
+                    continue;
+                }
+            }
+        }
+        let r = match flavor {
+            Utf8Flavor::Rfc2279 => sequence_rfc2279(&x[index..]),
+            _ => sequence(&x[index..], flavor),
+        };
+        let (len, surrogate) = match r {
+            Ok(r) => r,
+            Err((error_len, kind)) => {
+                // More input can only complete a pair if the truncated
+                // sequence may be a low surrogate.
+                let open = error_len.is_none() && low_surrogate(&x[index..]);
+                return Err(match high {
+                    Some(h) if pairs && open => {
+                        Error::new(h, None, ErrorKind::Truncated)
+                    }
+                    Some(h) if pairs => {
+                        Error::new(h, Some(3), ErrorKind::Surrogate)
+                    }
+                    _ => Error::new(index, error_len, kind),
+                });
+            }
+        };
+        match (high, surrogate) {
+            (Some(h), Some(Surrogate::Low)) => {
+                if !pairs {
+                    return Err(Error::new(h, Some(3), ErrorKind::Surrogate));
+                }
+                high = None;
+            }
+            (Some(h), _) if pairs => {
+                return Err(Error::new(h, Some(3), ErrorKind::Surrogate));
+            }
+            (None, Some(Surrogate::Low)) if pairs => {
+                return Err(Error::new(index, Some(3), ErrorKind::Surrogate));
+            }
+            (_, Some(Surrogate::High)) => high = Some(index),
+            _ => high = None,
+        }
+        index += len;
+    }
+    match high {
+        Some(h) if pairs => Err(Error::new(h, None, ErrorKind::Truncated)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    const FLAVORS: [Utf8Flavor; 5] = [
+        Utf8Flavor::Strict,
+        Utf8Flavor::Cesu8,
+        Utf8Flavor::ModifiedUtf8,
+        Utf8Flavor::Wtf8,
+        Utf8Flavor::Rfc2279,
+    ];
+
+    /// Returns `(valid_up_to, error_len, kind)` of the result.
+    fn check(
+        x: &[u8],
+        flavor: Utf8Flavor,
+    ) -> Option<(usize, Option<usize>, ErrorKind)> {
+        is_utf8_flavor(x, flavor)
+            .err()
+            .map(|e| (e.valid_up_to(), e.error_len(), e.kind()))
+    }
+
+    #[test]
+    fn test_common() {
+        let inputs: &[&[u8]] = &[
+            b"",
+            "abc κόσμε \u{20AC}".as_bytes(),
+            b"ab\x80cd",
+            b"ab\xC1\x80",
+            b"ab\xE0\x80\x80",
+            b"ab\xE2\x82",
+            b"ab\xE2\x41",
+            b"\xFE\x80",
+            b"\xFF",
+        ];
+        for x in inputs {
+            for &flavor in &FLAVORS {
+                assert_eq!(is_utf8_flavor(x, flavor), ::is_utf8(x));
+            }
+        }
+    }
+
+    #[test]
+    fn test_cesu8() {
+        use self::ErrorKind::*;
+        for &flavor in &[Utf8Flavor::Cesu8, Utf8Flavor::ModifiedUtf8] {
+            // U+10400 as a surrogate pair.
+            assert_eq!(check(b"a\xED\xA0\x81\xED\xB0\x80b", flavor), None);
+            assert_eq!(
+                check(b"a\xF0\x90\x90\x80", flavor),
+                Some((1, Some(4), Supplementary))
+            );
+            // Malformed 4-byte sequences are reported as by `is_utf8`.
+            let malformed: &[&[u8]] = &[
+                b"a\xF0\x80\x80\x80",
+                b"a\xF4\x90\x80\x80",
+                b"a\xF0\x41",
+                b"a\xF0\x90\x80",
+                b"a\xF5\x80\x80\x80",
+            ];
+            for x in malformed {
+                assert_eq!(is_utf8_flavor(x, flavor), ::is_utf8(x));
+            }
+            assert_eq!(
+                check(b"a\xED\xA0\x81b", flavor),
+                Some((1, Some(3), Surrogate))
+            );
+            assert_eq!(
+                check(b"a\xED\xA0\x81\xED\xA0\x81", flavor),
+                Some((1, Some(3), Surrogate))
+            );
+            assert_eq!(
+                check(b"a\xED\xB0\x80", flavor),
+                Some((1, Some(3), Surrogate))
+            );
+            assert_eq!(
+                check(b"a\xED\xA0\x81\xED\xB0", flavor),
+                Some((1, None, Truncated))
+            );
+            assert_eq!(
+                check(b"a\xED\xA0\x81", flavor),
+                Some((1, None, Truncated))
+            );
+            assert_eq!(
+                check(b"a\xED\xA0\x81\xED", flavor),
+                Some((1, None, Truncated))
+            );
+            // No more input makes these a low surrogate.
+            let unpaired: &[&[u8]] = &[
+                b"a\xED\xA0\x81\xE2\x82",
+                b"a\xED\xA0\x81\xED\xA0",
+                b"a\xED\xA0\x81\xF0\x90",
+            ];
+            for x in unpaired {
+                assert_eq!(check(x, flavor), Some((1, Some(3), Surrogate)));
+            }
+        }
+        assert_eq!(check(b"a\x00b", Utf8Flavor::Cesu8), None);
+    }
+
+    #[test]
+    fn test_modified_utf8() {
+        use self::ErrorKind::*;
+        let flavor = Utf8Flavor::ModifiedUtf8;
+        assert_eq!(check(b"a\xC0\x80b", flavor), None);
+        assert_eq!(check(b"a\x00b", flavor), Some((1, Some(1), Nul)));
+        assert_eq!(check(b"a\xC0\x81", flavor), Some((1, Some(1), Overlong)));
+        assert_eq!(check(b"a\xC0", flavor), Some((1, None, Truncated)));
+        assert_eq!(
+            check(b"a\xC0\x80", Utf8Flavor::Cesu8).unwrap().2,
+            Overlong
+        );
+    }
+
+    #[test]
+    fn test_wtf8() {
+        let flavor = Utf8Flavor::Wtf8;
+        assert_eq!(check(b"a\xED\xA0\x81b", flavor), None);
+        assert_eq!(check(b"a\xED\xB0\x80", flavor), None);
+        assert_eq!(check(b"\xED\xB0\x80\xED\xA0\x81", flavor), None);
+        assert_eq!(check(b"a\xF0\x90\x90\x80", flavor), None);
+        assert_eq!(
+            check(b"a\xED\xA0\x81\xED\xB0\x80", flavor),
+            Some((1, Some(3), ErrorKind::Surrogate))
+        );
+        assert_eq!(check(b"a\x00b", flavor), None);
+    }
+
+    #[test]
+    fn test_rfc2279() {
+        use self::ErrorKind::*;
+        let flavor = Utf8Flavor::Rfc2279;
+        let valid: &[&[u8]] = &[
+            b"\xED\xA0\x80",
+            b"\xF4\x90\x80\x80",
+            b"\xF7\xBF\xBF\xBF",
+            b"\xF8\x88\x80\x80\x80",
+            b"\xFC\x84\x80\x80\x80\x80",
+            b"a\xFD\xBF\xBF\xBF\xBF\xBFb",
+        ];
+        for x in valid {
+            assert_eq!(check(x, flavor), None, "{:x?}", x);
+        }
+        assert_eq!(
+            check(b"a\xF8\x87\xBF\xBF\xBF", flavor),
+            Some((1, Some(1), Overlong))
+        );
+        assert_eq!(
+            check(b"a\xFC\x83\xBF\xBF\xBF\xBF", flavor),
+            Some((1, Some(1), Overlong))
+        );
+        assert_eq!(
+            check(b"a\xFC\x84\x80\x41", flavor),
+            Some((1, Some(3), Truncated))
+        );
+        assert_eq!(
+            check(b"a\xFC\x84\x80", flavor),
+            Some((1, None, Truncated))
+        );
+        assert_eq!(
+            check(b"a\xFE", flavor),
+            Some((1, Some(1), InvalidLeadByte))
+        );
+    }
+}
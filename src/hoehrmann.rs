@@ -2,12 +2,19 @@
 //!
 //! http://bjoern.hoehrmann.de/utf-8/decoder/dfa/
 
-use ::{Utf8Error, Utf8ErrorImpl};
+use ::{Error, ErrorKind};
+use core::cmp;
 
-const UTF8_ACCEPT: u8 = 0;
-const UTF8_REJECT: u8 = 12;
+pub const UTF8_ACCEPT: u8 = 0;
+pub const UTF8_REJECT: u8 = 12;
+/// After `E0`: the next byte must be `A0..=BF`.
+const UTF8_AFTER_E0: u8 = 48;
+/// After `ED`: the next byte must be `80..=9F`.
+const UTF8_AFTER_ED: u8 = 60;
+/// After `F0`: the next byte must be `90..=BF`.
+const UTF8_AFTER_F0: u8 = 72;
 
-#[cfg_attr(rustfmt, rustfmt_skip)]
+#[rustfmt::skip]
 const UTF8D: [u8; 364] = [
     // The first part of the table maps bytes to character classes that
     // to reduce the size of the transition table and create bitmasks.
@@ -30,24 +37,78 @@ const UTF8D: [u8; 364] = [
 ];
 
 #[inline]
-unsafe fn decode(state: u8, byte: u8) -> u8 {
+pub unsafe fn decode(state: u8, byte: u8) -> u8 {
     *UTF8D.get_unchecked(256_usize + state as usize + UTF8D[byte as usize] as usize)
 }
 
+/// Like `decode`, accumulating the bits of the code point in `codep`.
 #[inline]
-pub fn is_utf8(x: &[u8]) -> Result<(), Utf8Error> {
+pub unsafe fn decode_codep(state: u8, codep: &mut u32, byte: u8) -> u8 {
+    let class = UTF8D[byte as usize];
+    *codep = if state == UTF8_ACCEPT {
+        (0xFF >> class) & byte as u32
+    } else {
+        (byte as u32 & 0x3F) | (*codep << 6)
+    };
+    *UTF8D.get_unchecked(256_usize + state as usize + class as usize)
+}
+
+/// Classifies the error of rejecting `byte` in `state`.
+#[cold]
+pub fn error_kind(state: u8, byte: u8) -> ErrorKind {
+    match (state, byte) {
+        (UTF8_ACCEPT, 0x80..=0xBF) => ErrorKind::UnexpectedContinuation,
+        (UTF8_ACCEPT, 0xC0) | (UTF8_ACCEPT, 0xC1) => ErrorKind::Overlong,
+        (UTF8_ACCEPT, _) => ErrorKind::InvalidLeadByte,
+        (_, 0x80..=0xBF) => match state {
+            UTF8_AFTER_E0 | UTF8_AFTER_F0 => ErrorKind::Overlong,
+            UTF8_AFTER_ED => ErrorKind::Surrogate,
+            // After `F4`: the next byte must be `80..=8F`.
+            _ => ErrorKind::OutOfRange,
+        },
+        _ => ErrorKind::Truncated,
+    }
+}
+
+/// Validates `x` with the DFA.
+///
+/// Runs of ASCII that start in the accepting state are skipped with the
+/// fastest ASCII kernel the CPU supports.
+#[inline]
+pub fn is_utf8(x: &[u8]) -> Result<(), Error> {
     let mut s = UTF8_ACCEPT;
     let mut first_not_ok = 0;
-    for i in 0..x.len() {
-        s = unsafe { decode(s, *x.get_unchecked(i)) };
+    let mut i = 0;
+    while i < x.len() {
+        let prev = s;
+        let byte = unsafe { *x.get_unchecked(i) };
+        if s == UTF8_ACCEPT && byte < 0x80 {
+            match ::runtime::is_ascii(&x[i..]) {
+                Ok(()) => return Ok(()),
+                Err(n) => {
+                    i += n;
+                    first_not_ok = i;
+                    continue;
+                }
+            }
+        }
+        s = unsafe { decode(s, byte) };
         match s {
             UTF8_ACCEPT => { first_not_ok = i + 1; },
-            UTF8_REJECT => return Err(Utf8ErrorImpl(first_not_ok, Some(1)).get()),
+            UTF8_REJECT => {
+                // The maximal prefix of a valid sequence is invalid, not
+                // including the byte that was rejected, unless it is the
+                // first one.
+                let error_len = cmp::max(1, i - first_not_ok) as u8;
+                let kind = error_kind(prev, byte);
+                return Err(Error::new(first_not_ok, Some(error_len), kind));
+            }
             _ => {},
         }
+        i += 1;
     }
     match s {
         UTF8_ACCEPT => Ok(()),
-        _ => return Err(Utf8ErrorImpl(first_not_ok, None).get()),
+        _ => Err(Error::new(first_not_ok, None, ErrorKind::Truncated)),
     }
 }
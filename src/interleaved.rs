@@ -0,0 +1,87 @@
+//! Björn Höhrmann’s algorithm over interleaved segments of the input.
+//!
+//! The DFA is latency-bound by the dependency of each state on the previous
+//! one. Running independent DFAs over `N` segments in the same loop hides
+//! that latency.
+
+use hoehrmann::{decode, UTF8_ACCEPT};
+use Error;
+
+/// Minimum number of bytes per segment.
+const MIN_SEGMENT_LEN: usize = 16;
+
+/// Returns the first character boundary at or after `i`, skipping at most
+/// 3 continuation bytes.
+///
+/// If `x` is valid, splitting it at such boundaries splits it into valid
+/// chunks. If `x` is invalid, some chunk is invalid, since valid chunks
+/// concatenate to valid UTF-8.
+pub fn char_start(x: &[u8], mut i: usize) -> usize {
+    for _ in 0..3 {
+        if i < x.len() && x[i] & 0xC0 == 0x80 {
+            i += 1;
+        }
+    }
+    i
+}
+
+fn validate<const N: usize>(x: &[u8]) -> Result<(), Error> {
+    if x.len() < N * MIN_SEGMENT_LEN {
+        return ::hoehrmann::is_utf8(x);
+    }
+
+    let len = x.len() / N;
+    let mut starts = [0; N];
+    for k in 1..N {
+        starts[k] = char_start(x, k * len).max(starts[k - 1]);
+    }
+    let end = |k: usize| if k + 1 < N { starts[k + 1] } else { x.len() };
+    let common = (0..N).map(|k| end(k) - starts[k]).min().unwrap();
+
+    let mut s = [UTF8_ACCEPT; N];
+    for i in 0..common {
+        for k in 0..N {
+            s[k] = unsafe { decode(s[k], *x.get_unchecked(starts[k] + i)) };
+        }
+    }
+    for k in 0..N {
+        for &b in &x[starts[k] + common..end(k)] {
+            s[k] = unsafe { decode(s[k], b) };
+        }
+    }
+
+    // The segments before the first invalid one are valid.
+    match s.iter().position(|&s| s != UTF8_ACCEPT) {
+        None => Ok(()),
+        Some(k) => {
+            let (start, end) = (starts[k], end(k));
+            let e = ::hoehrmann::is_utf8(&x[start..end]).unwrap_err();
+            if e.error_len().is_some() || end == x.len() {
+                return Err(e.offset(start));
+            }
+            // The segment ends in an incomplete character, that the next
+            // segment may complete.
+            let start = start + e.valid_up_to();
+            ::hoehrmann::is_utf8(&x[start..]).map_err(|e| e.offset(start))
+        }
+    }
+}
+
+/// Validates `x` with the Höhrmann DFA over 4 interleaved segments.
+///
+/// Returns the same result as `is_utf8_hoehrmann`.
+pub fn is_utf8(x: &[u8]) -> Result<(), Error> {
+    validate::<4>(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segments() {
+        ::testing::test_splices(6, validate::<2>);
+        ::testing::test_splices(6, validate::<3>);
+        ::testing::test_splices(6, validate::<4>);
+    }
+}
@@ -0,0 +1,620 @@
+//! `std::io` adapters.
+
+use rustc::utf8_char_width;
+use std::io::{self, BufRead, Read, Write};
+use {Error, Utf8Validator};
+
+impl From<Error> for io::Error {
+    /// Returns an error of kind `InvalidData` that wraps `e`.
+    fn from(e: Error) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Validation state of the reader adapters.
+#[derive(Debug, Default)]
+pub(crate) struct ReadState {
+    validator: Utf8Validator,
+    /// Number of bytes handed out.
+    pos: usize,
+    error: Option<Error>,
+}
+
+impl ReadState {
+    /// Returns the error the next read reports, if any.
+    pub(crate) fn error(&self) -> Option<Error> {
+        self.error
+    }
+
+    /// Validates a read of `n` bytes, of which `new` are the trailing bytes
+    /// not validated yet, and returns how many of them to hand out.
+    ///
+    /// A read of 0 bytes ends the stream.
+    pub(crate) fn check(&mut self, n: usize, new: &[u8]) -> io::Result<usize> {
+        let r = if n == 0 {
+            self.validator.finish()
+        } else {
+            self.validator.feed(new)
+        };
+        if let Err(e) = r {
+            self.error = Some(e);
+        }
+        self.available(n)
+    }
+
+    /// Validates a read like `check`, and returns how many bytes past those
+    /// handed out end at a character boundary and are valid.
+    ///
+    /// Fails if there are none and the stream is invalid.
+    pub(crate) fn check_complete(
+        &mut self,
+        n: usize,
+        new: &[u8],
+    ) -> io::Result<usize> {
+        let r = if n == 0 {
+            self.validator.finish()
+        } else {
+            self.validator.feed(new)
+        };
+        if let Err(e) = r {
+            self.error = Some(e);
+        }
+        self.complete()
+    }
+
+    /// Returns how many bytes past those handed out end at a character
+    /// boundary and are valid, like `check_complete` without reading.
+    pub(crate) fn complete(&self) -> io::Result<usize> {
+        let end = match self.error {
+            Some(e) => e.valid_up_to(),
+            None => self.validator.valid_up_to(),
+        };
+        match (end.saturating_sub(self.pos), self.error) {
+            (0, Some(e)) => Err(e.into()),
+            (n, _) => Ok(n),
+        }
+    }
+
+    /// Records that `n` bytes were handed out.
+    pub(crate) fn consume(&mut self, n: usize) {
+        self.pos += n;
+    }
+
+    /// Returns how many of the next `n` bytes to hand out.
+    fn available(&mut self, n: usize) -> io::Result<usize> {
+        match self.error {
+            None => Ok(n),
+            Some(e) => match e.valid_up_to().saturating_sub(self.pos) {
+                0 => Err(e.into()),
+                valid => Ok(valid.min(n)),
+            },
+        }
+    }
+}
+
+/// A reader that validates the bytes it reads from `R`.
+///
+/// Multi-byte characters may be split across reads. The valid bytes that
+/// precede an error are handed out first, the next read then fails with an
+/// `io::Error` of kind `InvalidData` that wraps an `Error` whose offsets are
+/// relative to the start of the stream.
+///
+/// `Read::read` only hands out complete characters: the leading bytes of a
+/// character split across reads are held back, at most 3 bytes, until the
+/// rest of it is read. `BufRead::fill_buf` cannot hold bytes back, since it
+/// returns the buffer of `R`, so the buffer it returns may end in up to
+/// 3 bytes of an invalid or truncated sequence that precede the error. If
+/// such leading bytes are consumed, the next `read` hands out the rest of
+/// their character.
+#[derive(Debug)]
+pub struct Utf8Reader<R> {
+    inner: R,
+    state: ReadState,
+    /// Number of bytes at the start of the buffer of `inner` that have
+    /// already been validated by `fill_buf`.
+    checked: usize,
+    /// Validated bytes read from `inner` but not handed out yet: an
+    /// incomplete character, or a character that did not fit in the buffer
+    /// of a read.
+    carry: [u8; 4],
+    carry_len: usize,
+}
+
+impl<R> Utf8Reader<R> {
+    /// Creates a reader that validates the bytes read from `inner`.
+    pub fn new(inner: R) -> Self {
+        Utf8Reader {
+            inner,
+            state: ReadState::default(),
+            checked: 0,
+            carry: [0; 4],
+            carry_len: 0,
+        }
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying reader.
+    ///
+    /// Bytes read directly from the underlying reader are not validated.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the underlying reader.
+    ///
+    /// Bytes held back by `read` are lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Hands out `n` bytes of the carry.
+    fn take_carry(&mut self, buf: &mut [u8], n: usize) -> usize {
+        buf[..n].copy_from_slice(&self.carry[..n]);
+        self.carry.copy_within(n..self.carry_len, 0);
+        self.carry_len -= n;
+        self.state.consume(n);
+        n
+    }
+}
+
+impl<R: Read> Read for Utf8Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let ready = self.state.complete()?.min(self.carry_len);
+            if ready > 0 {
+                return Ok(self.take_carry(buf, ready.min(buf.len())));
+            }
+
+            // The carry is an incomplete character, that the next bytes of
+            // `inner` complete or not.
+            let c = self.carry_len;
+            if buf.len() <= c {
+                // A character may not fit in `buf`: read it into the carry.
+                let n = self.inner.read(&mut self.carry[c..c + 1])?;
+                self.carry_len += n;
+                let checked = self.checked.min(n);
+                self.checked -= checked;
+                let new = &self.carry[c + checked..c + n];
+                let r = self.state.check_complete(n, new);
+                if self.state.error().is_some() {
+                    // The carry is an invalid sequence.
+                    self.carry_len = 0;
+                }
+                r?;
+                continue;
+            }
+            buf[..c].copy_from_slice(&self.carry[..c]);
+            let n = self.inner.read(&mut buf[c..])?;
+            // A `BufRead` hands out the bytes `fill_buf` validated first.
+            let checked = self.checked.min(n);
+            self.checked -= checked;
+            let end = c + n;
+            let k = self.state.check_complete(n, &buf[c + checked..end])?;
+            let k = k.min(end);
+            self.carry_len = 0;
+            if self.state.error().is_none() {
+                self.carry[..end - k].copy_from_slice(&buf[k..end]);
+                self.carry_len = end - k;
+            }
+            if k > 0 || n == 0 {
+                self.state.consume(k);
+                return Ok(k);
+            }
+        }
+    }
+}
+
+impl<R: BufRead> BufRead for Utf8Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.carry_len > 0 {
+            return Ok(&self.carry[..self.carry_len]);
+        }
+        let buf = self.inner.fill_buf()?;
+        let n = if self.state.error.is_some() {
+            self.state.available(buf.len())?
+        } else if buf.is_empty() || buf.len() > self.checked {
+            let new = &buf[self.checked.min(buf.len())..];
+            self.checked = buf.len();
+            self.state.check(buf.len(), new)?
+        } else {
+            buf.len()
+        };
+        Ok(&buf[..n])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.carry_len > 0 {
+            self.carry.copy_within(amt..self.carry_len, 0);
+            self.carry_len -= amt;
+            self.state.consume(amt);
+            return;
+        }
+        self.inner.consume(amt);
+        self.checked = self.checked.saturating_sub(amt);
+        self.state.consume(amt);
+    }
+}
+
+/// A writer that only forwards valid UTF-8 to `W`.
+///
+/// A write whose bytes would make the stream invalid forwards the valid
+/// bytes before the error, the next write then fails with an `io::Error` of
+/// kind `InvalidData` and forwards nothing. The writer stays usable after
+/// such an error: the rejected bytes are not part of the stream, and held
+/// bytes of a character that the write shows to be invalid are discarded,
+/// so that the next write starts at a character boundary.
+///
+/// The bytes of a character split across writes are held back, at most
+/// 3 bytes, until the character is complete. `flush` and `finish` fail if
+/// they are left with an incomplete character. Since `Drop` cannot report
+/// errors, dropping the writer silently discards such bytes: call `finish`
+/// to find out whether the stream ended in an incomplete character.
+#[derive(Debug)]
+pub struct Utf8Writer<W: Write> {
+    inner: W,
+    validator: Utf8Validator,
+    /// The bytes of the trailing incomplete character.
+    held: [u8; 3],
+}
+
+impl<W: Write> Utf8Writer<W> {
+    /// Creates a writer that forwards valid UTF-8 to `inner`.
+    pub fn new(inner: W) -> Self {
+        Utf8Writer {
+            inner,
+            validator: Utf8Validator::new(),
+            held: [0; 3],
+        }
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    ///
+    /// Bytes written directly to the underlying writer are not validated.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Flushes the writer and returns the underlying writer.
+    ///
+    /// Fails if the stream ends in an incomplete character, whose bytes are
+    /// discarded.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush().map(|()| self.inner)
+    }
+
+    fn held_len(&self) -> usize {
+        self.validator.incomplete_len()
+    }
+
+    /// Validates `buf` and returns the length of its valid prefix.
+    fn check(&self, buf: &[u8]) -> io::Result<(Utf8Validator, usize)> {
+        let mut v = self.validator;
+        match v.feed(buf) {
+            Ok(()) => Ok((v, buf.len())),
+            Err(e) => {
+                let fed = self.validator.valid_up_to() + self.held_len();
+                match e.valid_up_to().saturating_sub(fed) {
+                    0 => Err(e.into()),
+                    valid => {
+                        let mut v = self.validator;
+                        v.feed(&buf[..valid]).unwrap();
+                        Ok((v, valid))
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> Write for Utf8Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let held = self.held_len();
+        if held == 0 {
+            let (v, n) = self.check(buf)?;
+            let complete = n - v.incomplete_len();
+            self.get_mut().write_all(&buf[..complete])?;
+            self.held[..n - complete].copy_from_slice(&buf[complete..n]);
+            self.validator = v;
+            return Ok(n);
+        }
+
+        // Complete the held character first.
+        let width = utf8_char_width(self.held[0]);
+        let take = (width - held).min(buf.len());
+        let (v, n) = match self.check(&buf[..take]) {
+            Ok(r) => r,
+            Err(e) => {
+                self.validator.discard_incomplete();
+                return Err(e);
+            }
+        };
+        debug_assert_eq!(n, take);
+        if v.incomplete_len() != 0 {
+            self.held[held..held + n].copy_from_slice(&buf[..n]);
+            self.validator = v;
+            return Ok(n);
+        }
+        let mut c = [0; 4];
+        c[..held].copy_from_slice(&self.held[..held]);
+        c[held..width].copy_from_slice(&buf[..n]);
+        self.get_mut().write_all(&c[..width])?;
+        self.validator = v;
+        if n == buf.len() {
+            return Ok(n);
+        }
+        // Errors are reported again by the next write.
+        Ok(n + self.write(&buf[n..]).unwrap_or(0))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.get_mut().flush()?;
+        let mut v = self.validator;
+        v.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use std::prelude::v1::*;
+
+    /// Hands out `chunk` bytes per read.
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl<'a> Read for Chunked<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    fn unwrap_error(e: io::Error) -> Error {
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        *e.get_ref().unwrap().downcast_ref::<Error>().unwrap()
+    }
+
+    const INPUTS: &[&[u8]] = &[
+        b"",
+        b"a\xE2\x82\xAC\xF0\x9F\x98\x80 \xCE\xBA\xE1\xBD\xB9\xCF\x83\xCE\xBC",
+        b"abc\xF0\x9F\x98",
+        b"abc\xE2\x82\xACdef\xF0\x9F\x98\x41",
+        b"abc\xE2\x82\xACdef\xED\xA0\x80",
+        b"\x80",
+    ];
+
+    #[test]
+    fn test_read() {
+        for x in INPUTS {
+            let expected = ::is_utf8(x);
+            for chunk in 1..8 {
+                let inner = Chunked { data: x, chunk };
+                let mut v = Vec::new();
+                let r = Utf8Reader::new(inner).read_to_end(&mut v);
+                match expected {
+                    Ok(()) => assert_eq!(v, *x),
+                    Err(e) => {
+                        assert_eq!(unwrap_error(r.unwrap_err()), e);
+                        assert_eq!(v, &x[..e.valid_up_to()]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_small_buffers() {
+        for x in INPUTS {
+            let expected = ::is_utf8(x);
+            for chunk in 1..6 {
+                for len in 1..6 {
+                    let inner = Chunked { data: x, chunk };
+                    let mut reader = Utf8Reader::new(inner);
+                    let mut v = Vec::new();
+                    let mut buf = [0; 5];
+                    let r = loop {
+                        match reader.read(&mut buf[..len]) {
+                            Ok(0) => break Ok(()),
+                            Ok(n) => v.extend_from_slice(&buf[..n]),
+                            Err(e) => break Err(e),
+                        }
+                    };
+                    match expected {
+                        Ok(()) => assert_eq!(v, *x),
+                        Err(e) => {
+                            assert_eq!(unwrap_error(r.unwrap_err()), e);
+                            assert_eq!(v, &x[..e.valid_up_to()]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_buf_read() {
+        for x in INPUTS {
+            let expected = ::is_utf8(x);
+            for chunk in 1..8 {
+                for capacity in 1..8 {
+                    let inner = Chunked { data: x, chunk };
+                    let inner = BufReader::with_capacity(capacity, inner);
+                    let mut reader = Utf8Reader::new(inner);
+                    let mut v = Vec::new();
+                    let r = loop {
+                        let n = match reader.fill_buf() {
+                            Ok(buf) => {
+                                v.extend_from_slice(buf);
+                                buf.len()
+                            }
+                            Err(e) => break Err(e),
+                        };
+                        if n == 0 {
+                            break Ok(());
+                        }
+                        reader.consume(n);
+                    };
+                    match expected {
+                        Ok(()) => assert_eq!(v, *x),
+                        Err(e) => {
+                            assert_eq!(unwrap_error(r.unwrap_err()), e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mixed_read_and_buf_read() {
+        let inputs: &[&[u8]] = &[
+            b"a\xE2\x82\x41",
+            b"\xE2\x82\xAC\xE2\x82",
+            b"ab\xF0\x9F\x98\x80\xF0\x9F\x98\xCE\xBA",
+        ];
+        // A linear congruential generator picks the calls.
+        let mut seed = 1_u64;
+        let mut random = |n: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % n
+        };
+        for x in INPUTS.iter().chain(inputs) {
+            let expected = ::is_utf8(x);
+            for capacity in 1..8 {
+                for chunk in 1..4 {
+                    for _ in 0..20 {
+                        let inner = Chunked { data: x, chunk };
+                        let inner = BufReader::with_capacity(capacity, inner);
+                        let mut reader = Utf8Reader::new(inner);
+                        let mut v = Vec::new();
+                        let r = loop {
+                            let n = if random(2) == 0 {
+                                let buf = match reader.fill_buf() {
+                                    Ok(buf) => buf,
+                                    Err(e) => break Err(e),
+                                };
+                                if buf.is_empty() {
+                                    break Ok(());
+                                }
+                                let n = random(buf.len() + 1);
+                                v.extend_from_slice(&buf[..n]);
+                                n
+                            } else {
+                                let mut buf = [0; 5];
+                                let len = 1 + random(buf.len());
+                                match reader.read(&mut buf[..len]) {
+                                    Ok(0) => break Ok(()),
+                                    Ok(n) => {
+                                        v.extend_from_slice(&buf[..n]);
+                                        continue;
+                                    }
+                                    Err(e) => break Err(e),
+                                }
+                            };
+                            reader.consume(n);
+                        };
+                        assert_eq!(v, &x[..v.len()]);
+                        match expected {
+                            Ok(()) => {
+                                r.unwrap();
+                                assert_eq!(v.len(), x.len());
+                            }
+                            Err(e) => {
+                                assert_eq!(unwrap_error(r.unwrap_err()), e);
+                                // `fill_buf` may have handed out the leading
+                                // bytes of the invalid sequence.
+                                assert!(v.len() >= e.valid_up_to());
+                                assert!(v.len() <= e.valid_up_to() + 3);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_write() {
+        for x in INPUTS {
+            let expected = ::is_utf8(x);
+            for chunk in 1..8 {
+                let mut w = Utf8Writer::new(Vec::new());
+                let r = x.chunks(chunk).try_for_each(|c| w.write_all(c));
+                let out = w.get_ref().clone();
+                let end = w.finish();
+                match expected {
+                    Ok(()) => {
+                        r.unwrap();
+                        assert_eq!(end.unwrap(), *x);
+                    }
+                    Err(e) => {
+                        let err = match r {
+                            Ok(()) => end.unwrap_err(),
+                            Err(err) => err,
+                        };
+                        assert_eq!(unwrap_error(err), e);
+                        // Nothing past the valid prefix was forwarded.
+                        assert_eq!(out, &x[..e.valid_up_to()]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_after_error() {
+        let mut w = Utf8Writer::new(Vec::new());
+        assert_eq!(w.write(b"ab\xE2\x82").unwrap(), 4);
+        assert_eq!(w.get_ref(), b"ab");
+        assert!(w.flush().is_err());
+        assert_eq!(w.write(b"\xAC\xFFcd").unwrap(), 1);
+        assert!(w.write(b"\xFFcd").is_err());
+        assert_eq!(w.write(b"cd").unwrap(), 2);
+        assert_eq!(w.finish().unwrap(), "ab\u{20AC}cd".as_bytes());
+    }
+
+    #[test]
+    fn test_write_after_invalid_held_bytes() {
+        let mut w = Utf8Writer::new(Vec::new());
+        assert_eq!(w.write(b"ab\xE2\x82").unwrap(), 4);
+        let e = unwrap_error(w.write(b"A").unwrap_err());
+        assert_eq!((e.valid_up_to(), e.error_len()), (2, Some(2)));
+        w.flush().unwrap();
+        assert_eq!(w.write(b"cd").unwrap(), 2);
+        w.write_all("\u{20AC}".as_bytes()).unwrap();
+        assert_eq!(w.finish().unwrap(), "abcd\u{20AC}".as_bytes());
+    }
+
+    #[test]
+    fn test_drop_incomplete() {
+        let write = |w: &mut Utf8Writer<Vec<u8>>| -> io::Result<()> {
+            w.write_all(b"ok \xE2\x82")?;
+            w.write_all(b"A")
+        };
+        let mut w = Utf8Writer::new(Vec::new());
+        assert!(write(&mut w).is_err());
+        assert_eq!(w.get_ref(), b"ok ");
+        // Dropping the writer discards the held bytes.
+        drop(w);
+    }
+}
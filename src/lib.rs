@@ -10,35 +10,100 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(align_offset, stdsimd)]
-//#![no_std]
+#![no_std]
+
+#[cfg(any(feature = "std", test))]
+#[macro_use]
+extern crate std;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "async")]
+extern crate bytes;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+#[cfg(feature = "async")]
+extern crate tokio;
 
-extern crate stdsimd;
-
-use std as core;
-
-use core::str::Utf8Error;
 use core::{mem};
 
-use stdsimd::simd;
-
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod arch {
     #[cfg(target_arch = "x86")]
-    pub use ::stdsimd::arch::x86::*;
+    pub use core::arch::x86::*;
     #[cfg(target_arch = "x86_64")]
-    pub use ::stdsimd::arch::x86_64::*;
+    pub use core::arch::x86_64::*;
 }
 
+mod chars;
+mod convert;
+mod error;
+mod errors;
+#[cfg(feature = "mmap")]
+mod file;
+#[cfg(feature = "std")]
+mod io;
+mod location;
+#[cfg(feature = "alloc")]
+mod lossy;
 mod rustc;
+mod flavor;
 mod hoehrmann;
+mod interleaved;
 mod ascii;
+#[cfg(feature = "async")]
+mod async_io;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod lookup;
+mod mb3;
+#[cfg(feature = "std")]
+mod parallel;
+mod policy;
+mod runtime;
+mod shiftdfa;
+mod stream;
+#[cfg(test)]
+mod testing;
+mod trojan;
 
 pub use rustc::is_utf8 as is_utf8_rustc;
 pub use hoehrmann::is_utf8 as is_utf8_hoehrmann;
+pub use interleaved::is_utf8 as is_utf8_interleaved;
+pub use shiftdfa::is_utf8 as is_utf8_shiftdfa;
 pub use ascii::*;
+pub use chars::{chars, Chars};
+pub use convert::{from_utf8, from_utf8_mut};
+#[cfg(feature = "alloc")]
+pub use convert::from_utf8_vec;
+pub use error::{Error, ErrorKind};
+pub use errors::{errors, Errors};
+pub use flavor::{is_utf8_flavor, Utf8Flavor};
+#[cfg(feature = "alloc")]
+pub use errors::collect_errors;
+#[cfg(feature = "mmap")]
+pub use file::{validate_file, validate_open_file};
+#[cfg(feature = "std")]
+pub use io::{Utf8Reader, Utf8Writer};
+#[cfg(feature = "async")]
+pub use async_io::{AsyncUtf8Reader, Utf8Stream};
+pub use location::{location, Location};
+pub use mb3::is_utf8_mb3;
+#[cfg(feature = "alloc")]
+pub use lossy::{into_utf8_lossy, to_utf8_lossy, to_utf8_lossy_mb3};
+#[cfg(feature = "std")]
+pub use parallel::par_is_utf8;
+pub use policy::{is_utf8_policy, Policy};
+pub use stream::Utf8Validator;
+pub use trojan::{is_trojan_source_char, validate_trojan_source};
+#[cfg(feature = "alloc")]
+pub use trojan::trojan_source_offsets;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub use lookup::{is_utf8_lookup_avx2, is_utf8_lookup_sse41};
 
 /// Errors which can occur when attempting to interpret a sequence of u8 as a
 /// string containing ASCII characters.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct AsciiError(usize);
 
 impl AsciiError {
@@ -50,24 +115,30 @@ impl AsciiError {
     }
 }
 
-/// Workaround the internals of Utf8Error
-struct Utf8ErrorImpl(usize, Option<u8>);
-
-impl Utf8ErrorImpl {
-    fn get(self) -> Utf8Error {
-        unsafe { mem::transmute(self) }
-    }
-}
-
-pub fn is_utf8(x: &[u8]) -> Result<(), Utf8Error> {
-    let r = rustc::is_utf8(x);
+/// Returns `Ok` if `x` is valid UTF-8.
+///
+/// The kernel is chosen at runtime from the features supported by the CPU,
+/// falling back to `is_utf8_rustc`.
+pub fn is_utf8(x: &[u8]) -> Result<(), Error> {
+    let r = runtime::is_utf8(x);
+    debug_assert_eq!(rustc::is_utf8(x), r);
     debug_assert_eq!(hoehrmann::is_utf8(x), r);
+    debug_assert_eq!(shiftdfa::is_utf8(x), r);
     r
 }
 
+/// Returns `Ok` if `x` is ASCII.
+///
+/// The kernel is chosen at runtime from the features supported by the CPU,
+/// falling back to `is_ascii_scalar`.
+pub fn is_ascii(x: &[u8]) -> Result<(), AsciiError> {
+    runtime::is_ascii(x).map_err(AsciiError)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::is_utf8;
+    use super::*;
+    use std::prelude::v1::*;
 
     #[test]
     fn test_is_utf8() {
@@ -130,7 +201,7 @@ mod tests {
         assert!(is_utf8(&[0x80, 0xBF, 0x80, 0xBF, 0x80, 0xBF, 0x80]).is_err());
 
         // 3.1.9 Sequence of all 64 possible continuation bytes (0x80-0xbf):
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let continuation_bytes = [
             0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
             0x88, 0x89, 0x8A, 0x8B, 0x8C, 0x8D, 0x8E, 0x8F,
@@ -147,7 +218,7 @@ mod tests {
         }
 
         // 3.2  Lonely start characters
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let lonely_start_characters_2 = [
             0xC0, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7,
             0xC8, 0xC9, 0xCA, 0xCB, 0xCC, 0xCD, 0xCE, 0xCF,
@@ -159,7 +230,7 @@ mod tests {
             assert!(is_utf8(&[b]).is_err());
         }
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let lonely_start_characters_3 = [
             0xE0, 0xE1, 0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7,
             0xE8, 0xE9, 0xEA, 0xEB, 0xEC, 0xED, 0xEE, 0xEF,
@@ -169,7 +240,7 @@ mod tests {
             assert!(is_utf8(&[b]).is_err());
         }
 
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let lonely_start_characters_4 = [
             0xF0, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7,
         ];
@@ -203,7 +274,7 @@ mod tests {
         assert!(is_utf8(&[0xFD, 0xBF, 0xBF, 0xBF, 0xBF]).is_err());
 
         // 3.4 Concatenation of incomplete sequences
-        #[cfg_attr(rustfmt, rustfmt_skip)]
+        #[rustfmt::skip]
         let incomplete = [
             0xC0,
             0xE0, 0x80,
@@ -254,6 +325,119 @@ mod tests {
         assert!(is_utf8(&[0xed, 0xaf, 0xbf, 0xed, 0xbf, 0xbf]).is_err());
     }
 
+    type Backend = fn(&[u8]) -> Result<(), Error>;
+
+    /// Every validator, including the vector kernels supported by the CPU.
+    fn backends() -> Vec<(&'static str, Backend)> {
+        let mut backends: Vec<(&'static str, Backend)> = vec![
+            ("is_utf8", is_utf8),
+            ("rustc", is_utf8_rustc),
+            ("hoehrmann", is_utf8_hoehrmann),
+            ("shiftdfa", is_utf8_shiftdfa),
+            ("interleaved", is_utf8_interleaved),
+            ("stream", |x| {
+                let mut v = Utf8Validator::new();
+                v.feed(x)?;
+                v.finish()
+            }),
+            ("stream bytewise", |x| {
+                let mut v = Utf8Validator::new();
+                for b in x.chunks(1) {
+                    v.feed(b)?;
+                }
+                v.finish()
+            }),
+        ];
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            if is_x86_feature_detected!("sse4.1") {
+                backends.push(("lookup sse4.1", |x| unsafe {
+                    is_utf8_lookup_sse41(x)
+                }));
+            }
+            if is_x86_feature_detected!("avx2") {
+                backends.push(("lookup avx2", |x| unsafe {
+                    is_utf8_lookup_avx2(x)
+                }));
+            }
+        }
+        backends
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        use ErrorKind::*;
+        // from: http://www.cl.cam.ac.uk/~mgk25/ucs/examples/UTF-8-test.txt
+        #[rustfmt::skip]
+        let cases: &[(&[u8], usize, Option<usize>, ErrorKind)] = &[
+            // 2.3 Other boundary conditions
+            (&[0xF4, 0x90, 0x80, 0x80], 0, Some(1), OutOfRange),
+            (&[0xF4, 0xBF, 0xBF, 0xBF], 0, Some(1), OutOfRange),
+            (&[0xF7, 0xBF, 0xBF, 0xBF], 0, Some(1), InvalidLeadByte),
+            // 3.1 Unexpected continuation bytes
+            (&[0x80], 0, Some(1), UnexpectedContinuation),
+            (&[0xBF], 0, Some(1), UnexpectedContinuation),
+            (&[0x80, 0xBF, 0x80], 0, Some(1), UnexpectedContinuation),
+            (&[0xC2, 0x80, 0x80], 2, Some(1), UnexpectedContinuation),
+            (&[0xEF, 0xBF, 0xBF, 0xBF], 3, Some(1), UnexpectedContinuation),
+            // 3.2 Lonely start characters
+            (&[0xC0, 0x20], 0, Some(1), Overlong),
+            (&[0xC2, 0x20], 0, Some(1), Truncated),
+            (&[0xE0, 0x20], 0, Some(1), Truncated),
+            (&[0xED, 0x20], 0, Some(1), Truncated),
+            (&[0xF0, 0x20], 0, Some(1), Truncated),
+            (&[0xF4, 0x20], 0, Some(1), Truncated),
+            (&[0xF5, 0x20], 0, Some(1), InvalidLeadByte),
+            (&[0xF8, 0x20], 0, Some(1), InvalidLeadByte),
+            (&[0xFC, 0x20], 0, Some(1), InvalidLeadByte),
+            // 3.3 Sequences with last continuation byte missing
+            (&[0xC2], 0, None, Truncated),
+            (&[0xE0, 0xA0], 0, None, Truncated),
+            (&[0xF0, 0x90, 0x80], 0, None, Truncated),
+            (&[0xDF], 0, None, Truncated),
+            (&[0xEF, 0xBF], 0, None, Truncated),
+            (&[0xF4, 0x8F, 0xBF], 0, None, Truncated),
+            (&[0xE1, 0x80, 0x20], 0, Some(2), Truncated),
+            (&[0xF1, 0x80, 0x80, 0x20], 0, Some(3), Truncated),
+            (&[0xF8, 0x80, 0x80, 0x80], 0, Some(1), InvalidLeadByte),
+            // 3.5 Impossible bytes
+            (&[0xFE], 0, Some(1), InvalidLeadByte),
+            (&[0xFF], 0, Some(1), InvalidLeadByte),
+            // 4. Overlong sequences
+            (&[0xC0, 0xAF], 0, Some(1), Overlong),
+            (&[0xE0, 0x80, 0xAF], 0, Some(1), Overlong),
+            (&[0xF0, 0x80, 0x80, 0xAF], 0, Some(1), Overlong),
+            (&[0xF8, 0x80, 0x80, 0x80, 0xAF], 0, Some(1), InvalidLeadByte),
+            (&[0xC1, 0xBF], 0, Some(1), Overlong),
+            (&[0xE0, 0x9F, 0xBF], 0, Some(1), Overlong),
+            (&[0xF0, 0x8F, 0xBF, 0xBF], 0, Some(1), Overlong),
+            (&[0xC0, 0x80], 0, Some(1), Overlong),
+            // 5. Illegal code positions
+            (&[0xED, 0xA0, 0x80], 0, Some(1), Surrogate),
+            (&[0xED, 0xAD, 0xBF], 0, Some(1), Surrogate),
+            (&[0xED, 0xBF, 0xBF], 0, Some(1), Surrogate),
+            (&[0xED, 0xA0, 0x80, 0xED, 0xB0, 0x80], 0, Some(1), Surrogate),
+        ];
+        let text = "abcdefghijklmnopqrstuvwxyz0123456789κόσμε€😀";
+        for &(bytes, valid_up_to, error_len, kind) in cases {
+            // Also at an offset, after a prefix the vector kernels skip.
+            for &n in &[0, 1, 16, text.len()] {
+                let mut x = text.as_bytes()[..n].to_vec();
+                x.extend_from_slice(bytes);
+                for &(name, f) in &backends() {
+                    let e = f(&x).unwrap_err();
+                    assert_eq!(
+                        (e.valid_up_to(), e.error_len(), e.kind()),
+                        (n + valid_up_to, error_len, kind),
+                        "{}: {:x?}",
+                        name,
+                        x
+                    );
+                }
+            }
+        }
+    }
+
     const UTF8_SAMPLE_OK: &str = r#"
 UTF-8 encoded sample plain-text file
 ‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾‾
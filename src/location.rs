@@ -0,0 +1,179 @@
+//! Line and column numbers of byte offsets.
+
+use core::convert::TryInto;
+use core::{fmt, mem};
+use Error;
+
+/// Returns `x` with every byte set to `b`.
+#[inline]
+fn splat(b: u8) -> usize {
+    usize::from_ne_bytes([b; mem::size_of::<usize>()])
+}
+
+/// Returns the number of zero bytes of the word `x`.
+#[inline]
+fn count_zero_bytes(x: usize) -> u32 {
+    // The high bit of each byte of `y` is set if the byte of `x` is zero.
+    let y = (x & splat(0x7F)).wrapping_add(splat(0x7F));
+    (!(y | x | splat(0x7F))).count_ones()
+}
+
+/// Returns the number of continuation bytes of the word `x`.
+#[inline]
+fn count_continuation_bytes(x: usize) -> u32 {
+    (x & !(x << 1) & splat(0x80)).count_ones()
+}
+
+/// Returns the number of `\n` bytes in `x`.
+fn count_newlines(x: &[u8]) -> usize {
+    let words = x.chunks_exact(mem::size_of::<usize>());
+    let tail = words.remainder();
+    let mut n = 0;
+    for w in words {
+        let w = usize::from_ne_bytes(w.try_into().unwrap());
+        n += count_zero_bytes(w ^ splat(b'\n')) as usize;
+    }
+    n + tail.iter().filter(|&&b| b == b'\n').count()
+}
+
+/// Returns the number of characters of the valid UTF-8 `x`, that is, the
+/// number of bytes that are not continuation bytes.
+fn count_chars(x: &[u8]) -> usize {
+    let words = x.chunks_exact(mem::size_of::<usize>());
+    let tail = words.remainder();
+    let mut n = x.len();
+    for w in words {
+        let w = usize::from_ne_bytes(w.try_into().unwrap());
+        n -= count_continuation_bytes(w) as usize;
+    }
+    n - tail.iter().filter(|&&b| b & 0xC0 == 0x80).count()
+}
+
+/// The line and column of a byte of text.
+///
+/// Lines are separated by `\n` and all numbers are 1-based. `Display`
+/// prints `line:char_column`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Location {
+    line: usize,
+    column: usize,
+    char_column: usize,
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Location {
+    /// Returns the location of the first byte of a text.
+    pub fn new() -> Self {
+        Location {
+            line: 1,
+            column: 1,
+            char_column: 1,
+        }
+    }
+
+    /// Returns the line number.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the column, counting bytes.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    /// Returns the column, counting characters.
+    pub fn char_column(&self) -> usize {
+        self.char_column
+    }
+
+    /// Moves the location past `x`, the valid UTF-8 that follows it.
+    ///
+    /// Text that arrives in chunks can be passed one chunk at a time, even
+    /// if the chunks split characters.
+    pub fn advance(&mut self, x: &[u8]) {
+        let newlines = count_newlines(x);
+        let line = match newlines {
+            0 => x,
+            _ => {
+                self.line += newlines;
+                self.column = 1;
+                self.char_column = 1;
+                let last = x.iter().rposition(|&b| b == b'\n').unwrap();
+                &x[last + 1..]
+            }
+        };
+        self.column += line.len();
+        self.char_column += count_chars(line);
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.char_column)
+    }
+}
+
+/// Returns the location of the byte at `offset` of `x`, where
+/// `x[..offset]` is valid UTF-8.
+///
+/// # Panics
+///
+/// If `offset > x.len()`.
+pub fn location(x: &[u8], offset: usize) -> Location {
+    let mut l = Location::new();
+    l.advance(&x[..offset]);
+    l
+}
+
+impl Error {
+    /// Returns the location of this error in the `input` it was reported
+    /// for.
+    pub fn location(&self, input: &[u8]) -> Location {
+        location(input, self.valid_up_to())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    /// Returns the location of the end of the valid UTF-8 `s`.
+    fn naive(s: &str) -> (usize, usize, usize) {
+        let line = s.rsplit('\n').next().unwrap();
+        (
+            s.matches('\n').count() + 1,
+            line.len() + 1,
+            line.chars().count() + 1,
+        )
+    }
+
+    #[test]
+    fn test_location() {
+        let text = "κόσμε\n\na\u{10348}b \u{20AC}\r\nline three\n€€€€€€€€€";
+        for (i, _) in text.char_indices() {
+            let l = location(text.as_bytes(), i);
+            let expected = naive(&text[..i]);
+            assert_eq!((l.line(), l.column(), l.char_column()), expected);
+            // Chunks may split characters.
+            let mut m = Location::new();
+            for chunk in text.as_bytes()[..i].chunks(3) {
+                m.advance(chunk);
+            }
+            assert_eq!(m, l);
+        }
+    }
+
+    #[test]
+    fn test_error_location() {
+        let x = b"first\nsecond \xCE\xBA\xFF";
+        let l = ::is_utf8(x).unwrap_err().location(x);
+        assert_eq!((l.line(), l.column(), l.char_column()), (2, 10, 9));
+        assert_eq!(l.to_string(), "2:9");
+    }
+}
@@ -0,0 +1,351 @@
+//! Keiser and Lemire's lookup algorithm:
+//!
+//! https://arxiv.org/abs/2010.03090
+//!
+//! Every byte is checked together with the byte preceding it: three 16-entry
+//! tables indexed by the high and low nibble of the previous byte and the
+//! high nibble of the current byte are combined with a bitwise and, which is
+//! non-zero iff the two-byte window is invalid (too short, too long,
+//! overlong, surrogate, or too large). The remaining errors, missing or
+//! excess continuation bytes of 3- and 4-byte sequences, are found by
+//! looking two and three bytes back.
+//!
+//! The vector kernels only detect that a block contains an error. The block
+//! and the input tail are then handed to the scalar algorithm, starting from
+//! the last character boundary, so that the reported `Error` is exactly
+//! the one `rustc::is_utf8` would report.
+
+use Error;
+use rustc;
+
+/// 11______ 0_______ / 11______ 11______
+const TOO_SHORT: u8 = 1 << 0;
+/// 0_______ 10______
+const TOO_LONG: u8 = 1 << 1;
+/// 11100000 100_____
+const OVERLONG_3: u8 = 1 << 2;
+/// 11110100 1001____ / 11110100 101_____ / 11110101+ 1001____ / ..=
+const TOO_LARGE: u8 = 1 << 3;
+/// 11101101 101_____
+const SURROGATE: u8 = 1 << 4;
+/// 1100000_ 10______
+const OVERLONG_2: u8 = 1 << 5;
+/// 11110101+ 1000____
+const TOO_LARGE_1000: u8 = 1 << 6;
+/// 11110000 1000____
+const OVERLONG_4: u8 = 1 << 6;
+/// 10______ 10______
+const TWO_CONTS: u8 = 1 << 7;
+/// Errors that do not depend on the low nibble of the previous byte.
+const CARRY: u8 = TOO_SHORT | TOO_LONG | TWO_CONTS;
+
+/// Indexed by the high nibble of the previous byte.
+#[rustfmt::skip]
+static BYTE_1_HIGH: [u8; 16] = [
+    // 0_______ ________ <ASCII in byte 1>
+    TOO_LONG, TOO_LONG, TOO_LONG, TOO_LONG,
+    TOO_LONG, TOO_LONG, TOO_LONG, TOO_LONG,
+    // 10______ ________ <continuation in byte 1>
+    TWO_CONTS, TWO_CONTS, TWO_CONTS, TWO_CONTS,
+    // 1100____ ________ <two byte lead in byte 1>
+    TOO_SHORT | OVERLONG_2,
+    // 1101____ ________ <two byte lead in byte 1>
+    TOO_SHORT,
+    // 1110____ ________ <three byte lead in byte 1>
+    TOO_SHORT | OVERLONG_3 | SURROGATE,
+    // 1111____ ________ <four+ byte lead in byte 1>
+    TOO_SHORT | TOO_LARGE | TOO_LARGE_1000 | OVERLONG_4,
+];
+
+/// Indexed by the low nibble of the previous byte.
+#[rustfmt::skip]
+static BYTE_1_LOW: [u8; 16] = [
+    // ____0000 ________
+    CARRY | OVERLONG_3 | OVERLONG_2 | OVERLONG_4,
+    // ____0001 ________
+    CARRY | OVERLONG_2,
+    // ____001_ ________
+    CARRY,
+    CARRY,
+    // ____0100 ________
+    CARRY | TOO_LARGE,
+    // ____0101 ________
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    // ____011_ ________
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    // ____1___ ________
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    // ____1101 ________
+    CARRY | TOO_LARGE | TOO_LARGE_1000 | SURROGATE,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+    CARRY | TOO_LARGE | TOO_LARGE_1000,
+];
+
+/// Indexed by the high nibble of the current byte.
+#[rustfmt::skip]
+static BYTE_2_HIGH: [u8; 16] = [
+    // ________ 0_______ <ASCII in byte 2>
+    TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT,
+    TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT,
+    // ________ 1000____
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | OVERLONG_3 | TOO_LARGE_1000 | OVERLONG_4,
+    // ________ 1001____
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | OVERLONG_3 | TOO_LARGE,
+    // ________ 101_____
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | SURROGATE | TOO_LARGE,
+    TOO_LONG | OVERLONG_2 | TWO_CONTS | SURROGATE | TOO_LARGE,
+    // ________ 11______
+    TOO_SHORT, TOO_SHORT, TOO_SHORT, TOO_SHORT,
+];
+
+/// A block ending in one of these bytes (or larger ones) ends in the middle
+/// of a multi-byte sequence.
+#[rustfmt::skip]
+static MAX_COMPLETE: [u8; 16] = [
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xF0 - 1, 0xE0 - 1, 0xC0 - 1,
+];
+
+/// Validates `x[i..]` with the scalar algorithm, where `x[..i]` is known to
+/// be valid except for a trailing incomplete character.
+#[inline]
+fn validate_tail(x: &[u8], i: usize) -> Result<(), Error> {
+    // Back up to the lead byte of the character `x[i]` belongs to, if any.
+    let mut start = i;
+    for k in 1..4 {
+        if k > i || x[i - k] < 0x80 {
+            break;
+        }
+        if x[i - k] >= 0xC0 {
+            start = i - k;
+            break;
+        }
+    }
+    rustc::is_utf8(&x[start..]).map_err(|e| e.offset(start))
+}
+
+/// `_mm_shuffle_epi8` and `_mm_alignr_epi8` require SSSE3, `_mm_testz_si128`
+/// requires SSE4.1
+///
+/// # Safety
+///
+/// The CPU must support SSE4.1.
+#[target_feature(enable = "sse4.1")]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn is_utf8_lookup_sse41(x: &[u8]) -> Result<(), Error> {
+    use ::arch::*;
+
+    #[inline(always)]
+    unsafe fn load(t: &[u8; 16]) -> __m128i {
+        _mm_loadu_si128(t.as_ptr() as *const __m128i)
+    }
+    #[inline(always)]
+    unsafe fn high_nibbles(x: __m128i) -> __m128i {
+        _mm_and_si128(_mm_srli_epi16(x, 4), _mm_set1_epi8(0x0F))
+    }
+
+    let byte_1_high = load(&BYTE_1_HIGH);
+    let byte_1_low = load(&BYTE_1_LOW);
+    let byte_2_high = load(&BYTE_2_HIGH);
+    let max_complete = load(&MAX_COMPLETE);
+    let low_nibble_mask = _mm_set1_epi8(0x0F);
+    let cont_bit = _mm_set1_epi8(0x80_u8 as i8);
+
+    let ptr = x.as_ptr();
+    let len = x.len();
+    let mut i = 0;
+    let mut prev = _mm_setzero_si128();
+    let mut prev_incomplete = _mm_setzero_si128();
+    while i + 16 <= len {
+        let input = _mm_loadu_si128(ptr.add(i) as *const __m128i);
+        let error;
+        if _mm_movemask_epi8(input) == 0 {
+            // ASCII block: only a sequence left open by the previous block
+            // can be an error.
+            error = prev_incomplete;
+            prev_incomplete = _mm_setzero_si128();
+        } else {
+            let prev1 = _mm_alignr_epi8(input, prev, 15);
+            let special_cases = _mm_and_si128(
+                _mm_and_si128(
+                    _mm_shuffle_epi8(byte_1_high, high_nibbles(prev1)),
+                    _mm_shuffle_epi8(
+                        byte_1_low,
+                        _mm_and_si128(prev1, low_nibble_mask),
+                    ),
+                ),
+                _mm_shuffle_epi8(byte_2_high, high_nibbles(input)),
+            );
+            let prev2 = _mm_alignr_epi8(input, prev, 14);
+            let prev3 = _mm_alignr_epi8(input, prev, 13);
+            // Only 111_____ / 1111____ have the high bit set afterwards.
+            let must_be_2_3_continuation = _mm_or_si128(
+                _mm_subs_epu8(prev2, _mm_set1_epi8((0xE0 - 0x80) as i8)),
+                _mm_subs_epu8(prev3, _mm_set1_epi8((0xF0 - 0x80) as i8)),
+            );
+            error = _mm_xor_si128(
+                _mm_and_si128(must_be_2_3_continuation, cont_bit),
+                special_cases,
+            );
+            prev_incomplete = _mm_subs_epu8(input, max_complete);
+        }
+        if _mm_testz_si128(error, error) == 0 {
+            break;
+        }
+        prev = input;
+        i += 16;
+    }
+    validate_tail(x, i)
+}
+
+/// `_mm256_shuffle_epi8` and `_mm256_alignr_epi8` require AVX2
+///
+/// # Safety
+///
+/// The CPU must support AVX2.
+#[target_feature(enable = "avx2")]
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn is_utf8_lookup_avx2(x: &[u8]) -> Result<(), Error> {
+    use ::arch::*;
+
+    #[inline(always)]
+    unsafe fn load(t: &[u8; 16]) -> __m256i {
+        _mm256_broadcastsi128_si256(_mm_loadu_si128(
+            t.as_ptr() as *const __m128i
+        ))
+    }
+    #[inline(always)]
+    unsafe fn high_nibbles(x: __m256i) -> __m256i {
+        _mm256_and_si256(_mm256_srli_epi16(x, 4), _mm256_set1_epi8(0x0F))
+    }
+
+    let byte_1_high = load(&BYTE_1_HIGH);
+    let byte_1_low = load(&BYTE_1_LOW);
+    let byte_2_high = load(&BYTE_2_HIGH);
+    // Only the last three bytes of the upper lane matter here.
+    let max_complete = _mm256_inserti128_si256(
+        _mm256_set1_epi8(0xFF_u8 as i8),
+        _mm_loadu_si128(MAX_COMPLETE.as_ptr() as *const __m128i),
+        1,
+    );
+    let low_nibble_mask = _mm256_set1_epi8(0x0F);
+    let cont_bit = _mm256_set1_epi8(0x80_u8 as i8);
+
+    let ptr = x.as_ptr();
+    let len = x.len();
+    let mut i = 0;
+    let mut prev = _mm256_setzero_si256();
+    let mut prev_incomplete = _mm256_setzero_si256();
+    while i + 32 <= len {
+        let input =
+            _mm256_loadu_si256(ptr.add(i) as *const __m256i);
+        let error;
+        if _mm256_movemask_epi8(input) == 0 {
+            error = prev_incomplete;
+            prev_incomplete = _mm256_setzero_si256();
+        } else {
+            // The previous 16 bytes of each lane: the upper half of `prev`
+            // for the lower lane, the lower half of `input` for the upper.
+            let shifted = _mm256_permute2x128_si256(prev, input, 0x21);
+            let prev1 = _mm256_alignr_epi8(input, shifted, 15);
+            let special_cases = _mm256_and_si256(
+                _mm256_and_si256(
+                    _mm256_shuffle_epi8(byte_1_high, high_nibbles(prev1)),
+                    _mm256_shuffle_epi8(
+                        byte_1_low,
+                        _mm256_and_si256(prev1, low_nibble_mask),
+                    ),
+                ),
+                _mm256_shuffle_epi8(byte_2_high, high_nibbles(input)),
+            );
+            let prev2 = _mm256_alignr_epi8(input, shifted, 14);
+            let prev3 = _mm256_alignr_epi8(input, shifted, 13);
+            let must_be_2_3_continuation = _mm256_or_si256(
+                _mm256_subs_epu8(prev2, _mm256_set1_epi8((0xE0 - 0x80) as i8)),
+                _mm256_subs_epu8(prev3, _mm256_set1_epi8((0xF0 - 0x80) as i8)),
+            );
+            error = _mm256_xor_si256(
+                _mm256_and_si256(must_be_2_3_continuation, cont_bit),
+                special_cases,
+            );
+            prev_incomplete = _mm256_subs_epu8(input, max_complete);
+        }
+        if _mm256_testz_si256(error, error) == 0 {
+            break;
+        }
+        prev = input;
+        i += 32;
+    }
+    validate_tail(x, i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    /// Compares `f` against `rustc::is_utf8` with invalid and valid
+    /// sequences spliced into ASCII and non-ASCII text at every offset, so
+    /// that they straddle the block boundaries of the vector kernels.
+    fn test_against_rustc<F>(f: F)
+    where
+        F: Fn(&[u8]) -> Result<(), Error>,
+    {
+        #[rustfmt::skip]
+        let sequences: &[&[u8]] = &[
+            &[0xC2, 0x80], &[0xDF, 0xBF], &[0xC0, 0x80], &[0xC1, 0xBF],
+            &[0xE0, 0xA0, 0x80], &[0xE0, 0x9F, 0xBF], &[0xE1, 0x80, 0x41],
+            &[0xED, 0x9F, 0xBF], &[0xED, 0xA0, 0x80], &[0xEF, 0xBF, 0xBF],
+            &[0xF0, 0x90, 0x80, 0x80], &[0xF0, 0x8F, 0xBF, 0xBF],
+            &[0xF4, 0x8F, 0xBF, 0xBF], &[0xF4, 0x90, 0x80, 0x80],
+            &[0xF5, 0x80, 0x80, 0x80], &[0xF1, 0x80, 0x80],
+            &[0xF8, 0x88, 0x80, 0x80, 0x80], &[0x80, 0x80, 0x80, 0x80],
+            &[0xE1, 0x80, 0x80, 0x80], &[0xF1, 0x80, 0x80, 0x80, 0x80],
+        ];
+        let texts = [
+            "abcdefghijklmnopqrstuvwxyz".repeat(4),
+            "aé€😀".repeat(8),
+        ];
+        for text in texts.iter() {
+            let text = text.as_bytes();
+            for n in 0..text.len() {
+                assert_eq!(f(&text[..n]), rustc::is_utf8(&text[..n]));
+            }
+            for p in 0..text.len() {
+                let splice = |s: &[u8]| {
+                    let mut v = text[..p].to_vec();
+                    v.extend_from_slice(s);
+                    v.extend_from_slice(&text[p..]);
+                    assert_eq!(f(&v), rustc::is_utf8(&v), "{:x?}", v);
+                    v.truncate(p + s.len());
+                    assert_eq!(f(&v), rustc::is_utf8(&v), "{:x?}", v);
+                };
+                for b in 0x80..=0xFF_u8 {
+                    splice(&[b]);
+                }
+                for s in sequences {
+                    splice(s);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_is_utf8_lookup_sse41() {
+        if is_x86_feature_detected!("sse4.1") {
+            test_against_rustc(|x| unsafe { is_utf8_lookup_sse41(x) });
+        }
+    }
+
+    #[test]
+    fn test_is_utf8_lookup_avx2() {
+        if is_x86_feature_detected!("avx2") {
+            test_against_rustc(|x| unsafe { is_utf8_lookup_avx2(x) });
+        }
+    }
+}
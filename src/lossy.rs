@@ -0,0 +1,123 @@
+//! Lossy conversion, replacing invalid input with `U+FFFD`.
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::str;
+use Error;
+
+/// Converts `x` to a string, replacing each maximal invalid subsequence with
+/// `U+FFFD REPLACEMENT CHARACTER`, as the WHATWG Encoding Standard requires.
+///
+/// Valid input is returned as `Cow::Borrowed` without copying it.
+pub fn to_utf8_lossy<'a>(x: &'a [u8]) -> Cow<'a, str> {
+    match ::is_utf8(x) {
+        Ok(()) => Cow::Borrowed(unsafe { str::from_utf8_unchecked(x) }),
+        Err(e) => {
+            let mut s = String::with_capacity(x.len() + 2);
+            push_lossy(&mut s, x, e);
+            Cow::Owned(s)
+        }
+    }
+}
+
+/// Converts `x` to a string like `to_utf8_lossy`, reusing the allocation of
+/// `x` if it is valid.
+pub fn into_utf8_lossy(x: Vec<u8>) -> String {
+    match ::is_utf8(&x) {
+        Ok(()) => unsafe { String::from_utf8_unchecked(x) },
+        Err(e) => {
+            let mut s = String::with_capacity(x.len() + 2);
+            push_lossy(&mut s, &x, e);
+            s
+        }
+    }
+}
+
+/// Converts `x` to a string like `to_utf8_lossy`, also replacing each
+/// supplementary character with `U+FFFD`, so that the result is valid
+/// `utf8mb3`.
+pub fn to_utf8_lossy_mb3<'a>(x: &'a [u8]) -> Cow<'a, str> {
+    let s = to_utf8_lossy(x);
+    let i = match ::mb3::find_supplementary(s.as_bytes()) {
+        Some(i) => i,
+        None => return s,
+    };
+    let mut r = String::with_capacity(s.len());
+    r.push_str(&s[..i]);
+    r.extend(s[i..].chars().map(|c| match c {
+        '\u{10000}'..='\u{10FFFF}' => '\u{FFFD}',
+        c => c,
+    }));
+    Cow::Owned(r)
+}
+
+/// Appends `x`, whose first error is `e`, to `s`, replacing each maximal
+/// invalid subsequence with `U+FFFD`.
+fn push_lossy(s: &mut String, x: &[u8], mut e: Error) {
+    let mut pos = 0;
+    loop {
+        let valid = &x[pos..e.valid_up_to()];
+        s.push_str(unsafe { str::from_utf8_unchecked(valid) });
+        s.push('\u{FFFD}');
+        pos = match e.error_len() {
+            Some(len) => e.valid_up_to() + len,
+            None => return,
+        };
+        match ::is_utf8(&x[pos..]) {
+            Ok(()) => {
+                s.push_str(unsafe { str::from_utf8_unchecked(&x[pos..]) });
+                return;
+            }
+            Err(next) => e = next.offset(pos),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    #[test]
+    fn test_matches_std() {
+        let inputs: &[&[u8]] = &[
+            b"",
+            b"abc",
+            b"\xCE\xBA\xE1\xBD\xB9\xCF\x83\xCE\xBC\xCE\xB5",
+            b"\x80",
+            b"\xF0\x90\x80",
+            b"a\xC0\x80b\xED\xA0\x80c\xF4\x90\x80\x80d\xE1\x80",
+            b"\xF0\x90\x80\x41\xF0\x90\x80\xF0\x90\x80\x80",
+            b"\xFF\xFE abcdefghijklmnopqrstuvwxyz0123456789 \xC2",
+            b"\xF1\x80\x80\xE1\x80\xC2\x61\xE1\x80\x80\xF0\x80\x80",
+        ];
+        for x in inputs {
+            let expected = String::from_utf8_lossy(x);
+            assert_eq!(to_utf8_lossy(x), expected, "{:x?}", x);
+            assert_eq!(into_utf8_lossy(x.to_vec()), expected, "{:x?}", x);
+        }
+    }
+
+    #[test]
+    fn test_borrowed_if_valid() {
+        let x = "κόσμε".as_bytes();
+        let borrowed = match to_utf8_lossy(x) {
+            Cow::Borrowed(s) => s.as_ptr() == x.as_ptr(),
+            Cow::Owned(_) => false,
+        };
+        assert!(borrowed);
+        let v = x.to_vec();
+        let ptr = v.as_ptr();
+        let s = into_utf8_lossy(v);
+        assert_eq!(s.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_mb3() {
+        let x = b"a\xF0\x90\x8D\x88\xE2\x82\xAC\xF0\x90\x80b";
+        assert_eq!(to_utf8_lossy_mb3(x), "a\u{FFFD}\u{20AC}\u{FFFD}b");
+        let x = "κόσμε".as_bytes();
+        assert!(matches!(to_utf8_lossy_mb3(x), Cow::Borrowed(_)));
+    }
+}
@@ -0,0 +1,104 @@
+//! UTF-8 restricted to the Basic Multilingual Plane, as MySQL's `utf8mb3`.
+
+#[cfg(feature = "alloc")]
+use core::convert::TryInto;
+#[cfg(feature = "alloc")]
+use core::mem;
+use {Error, ErrorKind};
+
+#[cfg(feature = "alloc")]
+/// Returns `x` with every byte set to `b`.
+#[inline]
+fn splat(b: u8) -> usize {
+    usize::from_ne_bytes([b; mem::size_of::<usize>()])
+}
+
+#[cfg(feature = "alloc")]
+/// Returns the offset of the first byte of `x` that is at least `F0`, that
+/// is, of the first 4-byte sequence if `x` is valid UTF-8.
+pub fn find_supplementary(x: &[u8]) -> Option<usize> {
+    let words = x.chunks_exact(mem::size_of::<usize>());
+    let mut i = 0;
+    for w in words {
+        let w = usize::from_ne_bytes(w.try_into().unwrap());
+        // The high bit of a byte stays set if its 4 high bits are set.
+        if w & (w << 1) & (w << 2) & (w << 3) & splat(0x80) != 0 {
+            break;
+        }
+        i += mem::size_of::<usize>();
+    }
+    x[i..].iter().position(|&b| b >= 0xF0).map(|j| i + j)
+}
+
+/// Returns `Ok` if `x` is valid UTF-8 that only encodes characters of the
+/// Basic Multilingual Plane, in at most 3 bytes per character.
+///
+/// Errors have the same structure as those of `is_utf8`. A 4-byte sequence
+/// is an error of 4 bytes of kind `Supplementary`, as in `is_utf8_flavor`.
+///
+/// The plane is checked in the same pass as well-formedness.
+pub fn is_utf8_mb3(x: &[u8]) -> Result<(), Error> {
+    ::rustc::validate(x, false, |_, c| {
+        if c > '\u{FFFF}' {
+            Err(ErrorKind::Supplementary)
+        } else {
+            Ok(())
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_find_supplementary() {
+        let text = "a κόσμε \u{20AC}".repeat(3);
+        assert_eq!(find_supplementary(text.as_bytes()), None);
+        for i in 0..=text.len() {
+            let mut x = text.as_bytes()[..i].to_vec();
+            x.extend_from_slice("\u{10348}".as_bytes());
+            x.extend_from_slice(&text.as_bytes()[i..]);
+            assert_eq!(find_supplementary(&x), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_is_utf8_mb3() {
+        let check = |x: &[u8]| {
+            is_utf8_mb3(x)
+                .err()
+                .map(|e| (e.valid_up_to(), e.error_len(), e.kind()))
+        };
+        assert_eq!(check("a κόσμε \u{FFFF}".as_bytes()), None);
+        assert_eq!(
+            check(b"ab\xF0\x90\x8D\x88\xFF"),
+            Some((2, Some(4), ErrorKind::Supplementary))
+        );
+        let x = b"ab\xF0\x90\x8D\x88";
+        assert_eq!(is_utf8_mb3(x), ::is_utf8_flavor(x, ::Utf8Flavor::Cesu8));
+        assert_eq!(
+            check(b"ab\xFF\xF0\x90\x8D\x88"),
+            Some((2, Some(1), ErrorKind::InvalidLeadByte))
+        );
+        assert_eq!(
+            check(b"ab\xF0\x90\x8D"),
+            Some((2, None, ErrorKind::Truncated))
+        );
+        // The first error is reported, whichever kind it is.
+        let text = "a κόσμε \u{20AC}".repeat(3);
+        for i in 0..=text.len() {
+            if !text.is_char_boundary(i) {
+                continue;
+            }
+            let x = [&text[..i], "\u{10348}\u{FFFF}", &text[i..]].concat();
+            let mut x = x.into_bytes();
+            let e = Some((i, Some(4), ErrorKind::Supplementary));
+            assert_eq!(check(&x), e);
+            x.insert(i, 0x80);
+            assert_eq!(is_utf8_mb3(&x), ::is_utf8(&x));
+        }
+    }
+}
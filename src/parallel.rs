@@ -0,0 +1,89 @@
+//! Validation of large inputs on multiple threads.
+
+use interleaved::char_start;
+use std::iter;
+use std::thread;
+use std::vec::Vec;
+use Error;
+
+/// Minimum number of bytes a thread validates.
+const MIN_CHUNK_LEN: usize = 1 << 20;
+
+/// Checks whether `x` is valid UTF-8, validating chunks of it on as many
+/// threads as `std::thread::available_parallelism` reports.
+///
+/// Returns the same result as `is_utf8`. Inputs shorter than two chunks of
+/// 1 MiB are validated on the calling thread.
+pub fn par_is_utf8(x: &[u8]) -> Result<(), Error> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    validate(x, threads, MIN_CHUNK_LEN)
+}
+
+/// Validates `x` in at most `threads` chunks of at least `min_len` bytes.
+fn validate(x: &[u8], threads: usize, min_len: usize) -> Result<(), Error> {
+    let chunks = threads.min(x.len() / min_len);
+    if chunks <= 1 {
+        return ::is_utf8(x);
+    }
+
+    let len = x.len() / chunks;
+    let mut starts = Vec::with_capacity(chunks + 1);
+    starts.push(0);
+    for i in 1..chunks {
+        let prev = starts[i - 1];
+        starts.push(char_start(x, i * len).max(prev));
+    }
+    starts.push(x.len());
+
+    let results: Vec<Result<(), Error>> = thread::scope(|s| {
+        let handles: Vec<_> = starts[1..]
+            .windows(2)
+            .map(|w| {
+                let chunk = &x[w[0]..w[1]];
+                s.spawn(move || ::is_utf8(chunk))
+            })
+            .collect();
+        let first = ::is_utf8(&x[..starts[1]]);
+        iter::once(first)
+            .chain(handles.into_iter().map(|h| h.join().unwrap()))
+            .collect()
+    });
+
+    // The chunks before the first invalid one are valid, so it starts at a
+    // character boundary of `x`.
+    let (i, e) = match results.iter().enumerate().find(|r| r.1.is_err()) {
+        Some((i, r)) => (i, r.unwrap_err()),
+        None => return Ok(()),
+    };
+    let start = starts[i] + e.valid_up_to();
+    if e.error_len().is_some() {
+        return Err(e.offset(starts[i]));
+    }
+    // The chunk ends in an incomplete character that the next chunk may
+    // complete.
+    ::is_utf8(&x[start..]).map_err(|e| e.offset(start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    #[test]
+    fn test_validate() {
+        for &threads in &[2, 3, 7] {
+            for &min_len in &[1, 16] {
+                ::testing::test_splices(8, |x| validate(x, threads, min_len));
+            }
+        }
+    }
+
+    #[test]
+    fn test_par_is_utf8() {
+        let mut x = "κόσμε".repeat(MIN_CHUNK_LEN / 4).into_bytes();
+        assert_eq!(par_is_utf8(&x), Ok(()));
+        let i = x.len() - 3;
+        x[i] = 0xFF;
+        assert_eq!(par_is_utf8(&x), ::is_utf8(&x));
+    }
+}
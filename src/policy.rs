@@ -0,0 +1,154 @@
+//! Validation that also rejects categories of well-formed characters.
+
+use core::ops::{BitOr, BitOrAssign};
+use {Error, ErrorKind};
+
+/// A set of categories of characters to reject.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Policy(u8);
+
+impl Policy {
+    /// C0 controls (`U+0000..=U+001F`) other than tab, line feed and
+    /// carriage return, which XML 1.0 forbids.
+    pub const C0_CONTROLS: Policy = Policy(1);
+    /// C1 controls (`U+0080..=U+009F`).
+    pub const C1_CONTROLS: Policy = Policy(2);
+    /// Noncharacters: `U+FDD0..=U+FDEF` and the last two code points of
+    /// every plane, e.g. `U+FFFE` and `U+FFFF`.
+    pub const NONCHARACTERS: Policy = Policy(4);
+    /// Private-use characters: `U+E000..=U+F8FF` and planes 15 and 16.
+    pub const PRIVATE_USE: Policy = Policy(8);
+
+    /// Returns the policy that allows every character.
+    pub const fn empty() -> Policy {
+        Policy(0)
+    }
+
+    /// Returns the policy that rejects every category.
+    pub const fn all() -> Policy {
+        Policy(15)
+    }
+
+    /// Returns `true` if no category is rejected.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if every category of `other` is rejected.
+    pub const fn contains(self, other: Policy) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if `c` is in none of the rejected categories.
+    pub fn allows(self, c: char) -> bool {
+        let c = c as u32;
+        let category = match c {
+            0x00..=0x08 | 0x0B | 0x0C | 0x0E..=0x1F => Policy::C0_CONTROLS,
+            0x80..=0x9F => Policy::C1_CONTROLS,
+            0xFDD0..=0xFDEF => Policy::NONCHARACTERS,
+            _ if c & 0xFFFE == 0xFFFE => Policy::NONCHARACTERS,
+            0xE000..=0xF8FF | 0xF0000..=0x10FFFF => Policy::PRIVATE_USE,
+            _ => return true,
+        };
+        !self.contains(category)
+    }
+}
+
+impl BitOr for Policy {
+    type Output = Policy;
+
+    fn bitor(self, other: Policy) -> Policy {
+        Policy(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Policy {
+    fn bitor_assign(&mut self, other: Policy) {
+        self.0 |= other.0;
+    }
+}
+
+/// Returns `Ok` if `x` is valid UTF-8 that `policy` allows.
+///
+/// The policy is checked in the same pass as well-formedness. A character
+/// that `policy` rejects is an error of kind `Disallowed` that spans the
+/// character.
+pub fn is_utf8_policy(x: &[u8], policy: Policy) -> Result<(), Error> {
+    if policy.is_empty() {
+        return ::is_utf8(x);
+    }
+    let ascii = policy.contains(Policy::C0_CONTROLS);
+    ::rustc::validate(x, ascii, |_, c| {
+        if policy.allows(c) {
+            Ok(())
+        } else {
+            Err(ErrorKind::Disallowed(c))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    fn check(x: &[u8], policy: Policy) -> Option<(usize, Option<usize>)> {
+        is_utf8_policy(x, policy)
+            .err()
+            .map(|e| (e.valid_up_to(), e.error_len()))
+    }
+
+    #[test]
+    fn test_allows() {
+        let cases = [
+            ('\0', Policy::C0_CONTROLS),
+            ('\u{1F}', Policy::C0_CONTROLS),
+            ('\u{80}', Policy::C1_CONTROLS),
+            ('\u{9F}', Policy::C1_CONTROLS),
+            ('\u{FDD0}', Policy::NONCHARACTERS),
+            ('\u{FFFE}', Policy::NONCHARACTERS),
+            ('\u{10FFFF}', Policy::NONCHARACTERS),
+            ('\u{E000}', Policy::PRIVATE_USE),
+            ('\u{F0000}', Policy::PRIVATE_USE),
+            ('\u{10FFFD}', Policy::PRIVATE_USE),
+        ];
+        for &(c, category) in &cases {
+            assert!(!category.allows(c));
+            assert!(!Policy::all().allows(c));
+            let others = Policy(Policy::all().0 & !category.0);
+            assert!(others.allows(c));
+        }
+        for &c in &['\t', '\n', '\r', ' ', '\u{7F}', '\u{A0}', '\u{FFFD}'] {
+            assert!(Policy::all().allows(c));
+        }
+    }
+
+    #[test]
+    fn test_is_utf8_policy() {
+        let text = "a\tκόσμε\r\n\u{10348} \u{20AC}".repeat(4);
+        for &policy in &[Policy::empty(), Policy::all()] {
+            assert_eq!(check(text.as_bytes(), policy), None);
+        }
+        let inserts = ["\u{1}", "\u{85}", "\u{FFFF}", "\u{10FFFF}"];
+        for c in &inserts {
+            for i in 0..=text.len() {
+                if !text.is_char_boundary(i) {
+                    continue;
+                }
+                let x = [&text[..i], c, &text[i..]].concat();
+                let x = x.as_bytes();
+                assert_eq!(check(x, Policy::empty()), None);
+                let e = is_utf8_policy(x, Policy::all()).unwrap_err();
+                assert_eq!(e.valid_up_to(), i);
+                assert_eq!(e.error_len(), Some(c.len()));
+                let expected = c.chars().next().unwrap();
+                assert_eq!(e.kind(), ErrorKind::Disallowed(expected));
+            }
+        }
+        // Malformed input is reported as such.
+        assert_eq!(
+            is_utf8_policy(b"\x01\xC0\x80", Policy::C1_CONTROLS),
+            ::is_utf8(b"\x01\xC0\x80")
+        );
+    }
+}
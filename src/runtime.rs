@@ -0,0 +1,132 @@
+//! Runtime CPU feature detection.
+//!
+//! The best kernel for the running CPU is detected on the first call, and
+//! the function pointer is cached so that later calls only pay for an
+//! indirect call. Without `std` the CPU cannot be queried, and the kernels
+//! enabled at compile time are used instead.
+
+use Error;
+
+#[cfg(all(
+    feature = "std",
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+mod imp {
+    use core::mem;
+    use core::sync::atomic::{AtomicPtr, Ordering};
+    use ::{Error, ascii, lookup, rustc};
+
+    type Utf8Fn = fn(&[u8]) -> Result<(), Error>;
+    type AsciiFn = fn(&[u8]) -> Result<(), usize>;
+
+    static UTF8: AtomicPtr<()> = AtomicPtr::new(detect_utf8 as *mut ());
+    static ASCII: AtomicPtr<()> = AtomicPtr::new(detect_ascii as *mut ());
+
+    // The kernels are only ever called after detecting their features.
+    fn utf8_avx2(x: &[u8]) -> Result<(), Error> {
+        unsafe { lookup::is_utf8_lookup_avx2(x) }
+    }
+    fn utf8_sse41(x: &[u8]) -> Result<(), Error> {
+        unsafe { lookup::is_utf8_lookup_sse41(x) }
+    }
+    fn ascii_avx(x: &[u8]) -> Result<(), usize> {
+        unsafe { ascii::is_ascii_vector256_avx(x) }
+    }
+    fn ascii_sse41(x: &[u8]) -> Result<(), usize> {
+        unsafe { ascii::is_ascii_vector128_sse41(x) }
+    }
+
+    fn detect_utf8(x: &[u8]) -> Result<(), Error> {
+        let f: Utf8Fn = if is_x86_feature_detected!("avx2") {
+            utf8_avx2
+        } else if is_x86_feature_detected!("sse4.1") {
+            utf8_sse41
+        } else {
+            rustc::is_utf8
+        };
+        UTF8.store(f as *mut (), Ordering::Relaxed);
+        f(x)
+    }
+
+    fn detect_ascii(x: &[u8]) -> Result<(), usize> {
+        let f: AsciiFn = if is_x86_feature_detected!("avx") {
+            ascii_avx
+        } else if is_x86_feature_detected!("sse4.1") {
+            ascii_sse41
+        } else {
+            ascii::is_ascii_scalar
+        };
+        ASCII.store(f as *mut (), Ordering::Relaxed);
+        f(x)
+    }
+
+    #[inline]
+    pub fn is_utf8(x: &[u8]) -> Result<(), Error> {
+        let f = UTF8.load(Ordering::Relaxed);
+        unsafe { mem::transmute::<*mut (), Utf8Fn>(f)(x) }
+    }
+
+    #[inline]
+    pub fn is_ascii(x: &[u8]) -> Result<(), usize> {
+        let f = ASCII.load(Ordering::Relaxed);
+        unsafe { mem::transmute::<*mut (), AsciiFn>(f)(x) }
+    }
+}
+
+#[cfg(all(
+    not(feature = "std"),
+    any(target_arch = "x86", target_arch = "x86_64")
+))]
+mod imp {
+    use ::{Error, ascii, lookup, rustc};
+
+    #[inline]
+    pub fn is_utf8(x: &[u8]) -> Result<(), Error> {
+        if cfg!(target_feature = "avx2") {
+            unsafe { lookup::is_utf8_lookup_avx2(x) }
+        } else if cfg!(target_feature = "sse4.1") {
+            unsafe { lookup::is_utf8_lookup_sse41(x) }
+        } else {
+            rustc::is_utf8(x)
+        }
+    }
+
+    #[inline]
+    pub fn is_ascii(x: &[u8]) -> Result<(), usize> {
+        if cfg!(target_feature = "avx") {
+            unsafe { ascii::is_ascii_vector256_avx(x) }
+        } else if cfg!(target_feature = "sse4.1") {
+            unsafe { ascii::is_ascii_vector128_sse41(x) }
+        } else {
+            ascii::is_ascii_scalar(x)
+        }
+    }
+}
+
+/// Validates `x` with the fastest UTF-8 kernel supported by the CPU.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline]
+pub fn is_utf8(x: &[u8]) -> Result<(), Error> {
+    imp::is_utf8(x)
+}
+
+/// Validates `x` with the fastest UTF-8 kernel supported by the CPU.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+#[inline]
+pub fn is_utf8(x: &[u8]) -> Result<(), Error> {
+    ::rustc::is_utf8(x)
+}
+
+/// Validates `x` with the fastest ASCII kernel supported by the CPU.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[inline]
+pub fn is_ascii(x: &[u8]) -> Result<(), usize> {
+    imp::is_ascii(x)
+}
+
+/// Validates `x` with the fastest ASCII kernel supported by the CPU.
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+#[inline]
+pub fn is_ascii(x: &[u8]) -> Result<(), usize> {
+    ::ascii::is_ascii_scalar(x)
+}
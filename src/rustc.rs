@@ -1,9 +1,10 @@
 //! The rustc scalar algorithm in core
 
-use ::{Utf8Error, Utf8ErrorImpl, mem};
+use ::{Error, ErrorKind, mem};
+use core::{char, cmp};
 
 /// https://tools.ietf.org/html/rfc3629
-#[cfg_attr(rustfmt, rustfmt_skip)]
+#[rustfmt::skip]
 static UTF8_CHAR_WIDTH: [u8; 256] = [
     1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,
     1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1, // 0x1F
@@ -23,6 +24,13 @@ static UTF8_CHAR_WIDTH: [u8; 256] = [
     4,4,4,4,4,0,0,0,0,0,0,0,0,0,0,0, // 0xFF
 ];
 
+/// Returns the width of the character that starts with the lead byte `b`,
+/// or 0 if `b` cannot start a character.
+#[inline]
+pub fn utf8_char_width(b: u8) -> usize {
+    UTF8_CHAR_WIDTH[b as usize] as usize
+}
+
 /// Mask of the value bits of a continuation byte.
 const CONT_MASK: u8 = 0b0011_1111;
 /// Value of the tag bits (tag mask is !CONT_MASK) of a continuation byte.
@@ -37,8 +45,26 @@ fn contains_nonascii(x: usize) -> bool {
     (x & NONASCII_MASK) != 0
 }
 
+/// Returns `true` if any byte in the word `x` is below 0x20.
+#[inline]
+fn contains_control(x: usize) -> bool {
+    (x.wrapping_sub(NONASCII_MASK >> 2) & !x & NONASCII_MASK) != 0
+}
+
 #[inline]
-pub fn is_utf8(v: &[u8]) -> Result<(), Utf8Error> {
+pub fn is_utf8(v: &[u8]) -> Result<(), Error> {
+    validate(v, false, |_, _| Ok(()))
+}
+
+/// Validates `v`, calling `visit` with the offset of every non-ASCII
+/// character, and of every ASCII one if `ascii` is set.
+///
+/// An error returned by `visit` is reported for the whole character.
+#[inline(always)]
+pub fn validate<F>(v: &[u8], ascii: bool, mut visit: F) -> Result<(), Error>
+where
+    F: FnMut(usize, char) -> Result<(), ErrorKind>,
+{
     let mut index = 0;
     let len = v.len();
 
@@ -53,8 +79,8 @@ pub fn is_utf8(v: &[u8]) -> Result<(), Utf8Error> {
     while index < len {
         let old_offset = index;
         macro_rules! err {
-            ($error_len:expr) => {
-                return Err(Utf8ErrorImpl(old_offset, $error_len).get());
+            ($error_len:expr, $kind:expr) => {
+                return Err(Error::new(old_offset, $error_len, $kind))
             };
         }
 
@@ -63,7 +89,7 @@ pub fn is_utf8(v: &[u8]) -> Result<(), Utf8Error> {
                 index += 1;
                 // we needed data, but there was none: error!
                 if index >= len {
-                    err!(None)
+                    err!(None, ErrorKind::Truncated)
                 }
                 v[index]
             }};
@@ -92,35 +118,59 @@ pub fn is_utf8(v: &[u8]) -> Result<(), Utf8Error> {
             //               %xF4 %x80-8F 2( UTF8-tail )
             match w {
                 2 => if next!() & !CONT_MASK != TAG_CONT_U8 {
-                    err!(Some(1))
+                    err!(Some(1), ErrorKind::Truncated)
                 },
                 3 => {
                     match (first, next!()) {
-                        (0xE0, 0xA0...0xBF)
-                        | (0xE1...0xEC, 0x80...0xBF)
-                        | (0xED, 0x80...0x9F)
-                        | (0xEE...0xEF, 0x80...0xBF) => {}
-                        _ => err!(Some(1)),
+                        (0xE0, 0xA0..=0xBF)
+                        | (0xE1..=0xEC, 0x80..=0xBF)
+                        | (0xED, 0x80..=0x9F)
+                        | (0xEE..=0xEF, 0x80..=0xBF) => {}
+                        (0xE0, 0x80..=0x9F) => {
+                            err!(Some(1), ErrorKind::Overlong)
+                        }
+                        (0xED, 0xA0..=0xBF) => {
+                            err!(Some(1), ErrorKind::Surrogate)
+                        }
+                        _ => err!(Some(1), ErrorKind::Truncated),
                     }
                     if next!() & !CONT_MASK != TAG_CONT_U8 {
-                        err!(Some(2))
+                        err!(Some(2), ErrorKind::Truncated)
                     }
                 }
                 4 => {
                     match (first, next!()) {
-                        (0xF0, 0x90...0xBF)
-                        | (0xF1...0xF3, 0x80...0xBF)
-                        | (0xF4, 0x80...0x8F) => {}
-                        _ => err!(Some(1)),
+                        (0xF0, 0x90..=0xBF)
+                        | (0xF1..=0xF3, 0x80..=0xBF)
+                        | (0xF4, 0x80..=0x8F) => {}
+                        (0xF0, 0x80..=0x8F) => {
+                            err!(Some(1), ErrorKind::Overlong)
+                        }
+                        (0xF4, 0x90..=0xBF) => {
+                            err!(Some(1), ErrorKind::OutOfRange)
+                        }
+                        _ => err!(Some(1), ErrorKind::Truncated),
                     }
                     if next!() & !CONT_MASK != TAG_CONT_U8 {
-                        err!(Some(2))
+                        err!(Some(2), ErrorKind::Truncated)
                     }
                     if next!() & !CONT_MASK != TAG_CONT_U8 {
-                        err!(Some(3))
+                        err!(Some(3), ErrorKind::Truncated)
                     }
                 }
-                _ => err!(Some(1)),
+                _ => err!(Some(1), match first {
+                    0x80..=0xBF => ErrorKind::UnexpectedContinuation,
+                    0xC0 | 0xC1 => ErrorKind::Overlong,
+                    _ => ErrorKind::InvalidLeadByte,
+                }),
+            }
+            let mut c = (first & (0x7F >> w)) as u32;
+            for &b in unsafe { v.get_unchecked(old_offset + 1..=index) } {
+                c = c << 6 | (b & CONT_MASK) as u32;
+            }
+            let c = unsafe { char::from_u32_unchecked(c) };
+            if let Err(kind) = visit(old_offset, c) {
+                err!(Some(w), kind)
             }
             index += 1;
         } else {
@@ -130,26 +180,44 @@ pub fn is_utf8(v: &[u8]) -> Result<(), Utf8Error> {
             let ptr = v.as_ptr();
             let align = unsafe {
                 // the offset is safe, because `index` is guaranteed inbounds
-                ptr.offset(index as isize).align_offset(usize_bytes)
+                ptr.add(index).align_offset(usize_bytes)
             };
             if align == 0 {
                 while index < blocks_end {
                     unsafe {
-                        let block = ptr.offset(index as isize) as *const usize;
-                        // break if there is a nonascii byte
-                        let zu = contains_nonascii(*block);
-                        let zv = contains_nonascii(*block.offset(1));
+                        let block = ptr.add(index) as *const usize;
+                        // break if there is a nonascii byte, or a control
+                        // byte to visit
+                        let (x, y) = (*block, *block.add(1));
+                        let zu = contains_nonascii(x);
+                        let zv = contains_nonascii(y);
                         if zu | zv {
                             break;
                         }
+                        let zc = contains_control(x) | contains_control(y);
+                        if ascii && zc {
+                            break;
+                        }
                     }
                     index += ascii_block_size;
                 }
-                // step from the point where the wordwise loop stopped
-                while index < len && v[index] < 128 {
+                // step from the point where the wordwise loop stopped, up
+                // to the end of the block that stopped it
+                let end = cmp::min(len, index + ascii_block_size);
+                while index < end && v[index] < 128 {
+                    if ascii {
+                        if let Err(kind) = visit(index, v[index] as char) {
+                            return Err(Error::new(index, Some(1), kind));
+                        }
+                    }
                     index += 1;
                 }
             } else {
+                if ascii {
+                    if let Err(kind) = visit(index, first as char) {
+                        err!(Some(1), kind)
+                    }
+                }
                 index += 1;
             }
         }
@@ -0,0 +1,126 @@
+//! Shift-based DFA:
+//!
+//! https://gist.github.com/pervognsen/218ea17743e1442e59bb60d29b1aa725
+//!
+//! A state is the offset of a 6-bit field in a `u64`. The transitions of
+//! every state on a byte are packed into the row of the byte, so that a
+//! transition is one load and one shift.
+
+use Error;
+
+/// The rejecting state. It is 0 so that every row maps it to itself.
+const ERROR: u64 = 0;
+const ACCEPT: u64 = 6;
+/// One continuation byte left.
+const TAIL1: u64 = 12;
+/// Two continuation bytes left.
+const TAIL2: u64 = 18;
+/// Three continuation bytes left.
+const TAIL3: u64 = 24;
+/// After `E0`: the next byte must be `A0..=BF`.
+const AFTER_E0: u64 = 30;
+/// After `ED`: the next byte must be `80..=9F`.
+const AFTER_ED: u64 = 36;
+/// After `F0`: the next byte must be `90..=BF`.
+const AFTER_F0: u64 = 42;
+/// After `F4`: the next byte must be `80..=8F`.
+const AFTER_F4: u64 = 48;
+
+const fn row(b: u8) -> u64 {
+    let from_accept = match b {
+        0x00..=0x7F => ACCEPT,
+        0xC2..=0xDF => TAIL1,
+        0xE0 => AFTER_E0,
+        0xE1..=0xEC | 0xEE..=0xEF => TAIL2,
+        0xED => AFTER_ED,
+        0xF0 => AFTER_F0,
+        0xF1..=0xF3 => TAIL3,
+        0xF4 => AFTER_F4,
+        _ => ERROR,
+    };
+    let mut row = from_accept << ACCEPT;
+    if let 0x80..=0xBF = b {
+        row |= ACCEPT << TAIL1 | TAIL1 << TAIL2 | TAIL2 << TAIL3;
+    }
+    if let 0xA0..=0xBF = b {
+        row |= TAIL1 << AFTER_E0;
+    }
+    if let 0x80..=0x9F = b {
+        row |= TAIL1 << AFTER_ED;
+    }
+    if let 0x90..=0xBF = b {
+        row |= TAIL2 << AFTER_F0;
+    }
+    if let 0x80..=0x8F = b {
+        row |= TAIL2 << AFTER_F4;
+    }
+    row
+}
+
+const fn rows() -> [u64; 256] {
+    let mut rows = [0; 256];
+    let mut b = 0;
+    while b < 256 {
+        rows[b] = row(b as u8);
+        b += 1;
+    }
+    rows
+}
+
+static ROWS: [u64; 256] = rows();
+
+/// Number of bytes between checks for the rejecting state.
+const CHUNK: usize = 64;
+
+#[inline]
+pub fn is_utf8(x: &[u8]) -> Result<(), Error> {
+    // Only the low 6 bits of the state are meaningful, and the shift only
+    // uses those.
+    let mut s = ACCEPT;
+    // The end of the last chunk after which the state accepted.
+    let mut valid = 0;
+    for (i, chunk) in x.chunks(CHUNK).enumerate() {
+        for &b in chunk {
+            s = ROWS[b as usize].wrapping_shr(s as u32);
+        }
+        match s & 63 {
+            ACCEPT => valid = i * CHUNK + chunk.len(),
+            ERROR => break,
+            _ => {}
+        }
+    }
+    if s & 63 == ACCEPT {
+        return Ok(());
+    }
+    // `x[..valid]` is valid and ends at a character boundary.
+    ::hoehrmann::is_utf8(&x[valid..]).map_err(|e| e.offset(valid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    #[test]
+    fn test_splices() {
+        // Long enough for errors in and after the second and third chunks.
+        ::testing::test_splices(8, is_utf8);
+    }
+
+    #[test]
+    fn test_chunk_boundaries() {
+        // A character that straddles the end of the first chunk, so that the
+        // state does not accept after it.
+        let mut text = vec![b'a'; 3 * CHUNK];
+        text[CHUNK - 2..CHUNK + 2].copy_from_slice("\u{10348}".as_bytes());
+        assert_eq!(is_utf8(&text), Ok(()));
+        for i in CHUNK + 2..text.len() {
+            let mut x = text.clone();
+            x[i] = 0xFF;
+            assert_eq!(is_utf8(&x), ::rustc::is_utf8(&x), "{}", i);
+            x[i] = 0xE2;
+            let x = &x[..=i];
+            assert_eq!(is_utf8(x), ::rustc::is_utf8(x), "{}", i);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Incremental validation of input that arrives in chunks.
+
+use ::{Error, ErrorKind};
+use hoehrmann::{decode, error_kind, UTF8_ACCEPT, UTF8_REJECT};
+
+/// Validates a stream of bytes that arrives in chunks.
+///
+/// A multi-byte character may be split across chunks: the validator
+/// remembers the Höhrmann DFA state of a trailing incomplete character and
+/// resumes from it on the next call to `feed`. The offsets of the errors it
+/// reports are relative to the start of the stream.
+///
+/// Once an error has been reported, every later call reports it again.
+#[derive(Copy, Clone, Debug)]
+pub struct Utf8Validator {
+    /// DFA state after the bytes fed so far.
+    state: u8,
+    /// Number of bytes fed so far.
+    offset: usize,
+    /// Number of bytes of the trailing incomplete character.
+    pending: usize,
+    error: Option<Error>,
+}
+
+impl Default for Utf8Validator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Utf8Validator {
+    /// Creates a validator at the start of a stream.
+    pub fn new() -> Self {
+        Utf8Validator {
+            state: UTF8_ACCEPT,
+            offset: 0,
+            pending: 0,
+            error: None,
+        }
+    }
+
+    /// Returns the number of bytes of the stream known to be valid, that is,
+    /// the bytes fed so far minus a trailing incomplete character.
+    pub fn valid_up_to(&self) -> usize {
+        self.offset - self.pending
+    }
+
+    /// Returns the number of bytes of the trailing incomplete character.
+    #[cfg(feature = "std")]
+    pub(crate) fn incomplete_len(&self) -> usize {
+        self.pending
+    }
+
+    /// Drops the trailing incomplete character, as if it had not been fed.
+    #[cfg(feature = "std")]
+    pub(crate) fn discard_incomplete(&mut self) {
+        self.offset -= self.pending;
+        self.pending = 0;
+        self.state = UTF8_ACCEPT;
+    }
+
+    /// Validates the next chunk of the stream.
+    ///
+    /// Returns `Ok` if the stream is valid so far, where the stream may end
+    /// in an incomplete character that the next chunk has to complete.
+    pub fn feed(&mut self, x: &[u8]) -> Result<(), Error> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+
+        // Complete the character left open by the previous chunk.
+        let mut i = 0;
+        while self.state != UTF8_ACCEPT && i < x.len() {
+            let prev = self.state;
+            self.state = unsafe { decode(self.state, x[i]) };
+            if self.state == UTF8_REJECT {
+                let start = self.offset + i - self.pending;
+                let error_len = Some(self.pending as u8);
+                let kind = error_kind(prev, x[i]);
+                return self.fail(Error::new(start, error_len, kind));
+            }
+            i += 1;
+            self.pending += 1;
+        }
+        if self.state != UTF8_ACCEPT {
+            self.offset += i;
+            return Ok(());
+        }
+
+        match ::is_utf8(&x[i..]) {
+            Ok(()) => {
+                self.pending = 0;
+            }
+            Err(e) => {
+                if e.error_len().is_some() {
+                    return self.fail(e.offset(self.offset + i));
+                }
+                let valid_up_to = i + e.valid_up_to();
+                // The chunk ends in an incomplete character.
+                let tail = &x[valid_up_to..];
+                for &b in tail {
+                    self.state = unsafe { decode(self.state, b) };
+                }
+                self.pending = tail.len();
+            }
+        }
+        self.offset += x.len();
+        Ok(())
+    }
+
+    /// Ends the stream, returning an error if it ends in an incomplete
+    /// character.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        if self.state != UTF8_ACCEPT {
+            let start = self.offset - self.pending;
+            return self.fail(Error::new(start, None, ErrorKind::Truncated));
+        }
+        Ok(())
+    }
+
+    fn fail(&mut self, e: Error) -> Result<(), Error> {
+        self.error = Some(e);
+        Err(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    fn validate_chunks(chunks: &[&[u8]]) -> Result<(), Error> {
+        let mut v = Utf8Validator::new();
+        for c in chunks {
+            v.feed(c)?;
+        }
+        v.finish()
+    }
+
+    #[test]
+    fn test_split_everywhere() {
+        let mut inputs: Vec<Vec<u8>> = vec![
+            "aé€😀 κόσμε".as_bytes().to_vec(),
+            vec![0x41, 0xF0, 0x90, 0x80],
+            vec![0x41, 0xF0, 0x90, 0x80, 0x41],
+            vec![0x41, 0xE0, 0x80, 0x80],
+            vec![0xED, 0xA0, 0x80, 0x41],
+            vec![0xF4, 0x8F, 0xBF, 0xBF, 0xC2],
+            vec![0xE1, 0x80, 0x41, 0x80],
+            vec![0xC2, 0x80, 0x80],
+        ];
+        let prefix = "abcdefghijklmnopqrstuvwxyzé€".repeat(3);
+        for i in 0..inputs.len() {
+            let mut v = prefix.as_bytes().to_vec();
+            v.extend_from_slice(&inputs[i]);
+            inputs.push(v);
+        }
+        for x in &inputs {
+            let expected = ::rustc::is_utf8(x);
+            for i in 0..=x.len() {
+                for j in i..=x.len() {
+                    let r = validate_chunks(&[&x[..i], &x[i..j], &x[j..]]);
+                    assert_eq!(r, expected, "{:x?} split at {}, {}", x, i, j);
+                }
+            }
+            let bytes: Vec<&[u8]> = x.chunks(1).collect();
+            assert_eq!(validate_chunks(&bytes), expected);
+        }
+    }
+
+    #[test]
+    fn test_error_is_sticky() {
+        let mut v = Utf8Validator::new();
+        assert!(v.feed(b"ab").is_ok());
+        let e = v.feed(&[0x80]).unwrap_err();
+        assert_eq!(e.valid_up_to(), 2);
+        assert_eq!(v.feed(b"cd"), Err(e));
+        assert_eq!(v.finish(), Err(e));
+    }
+
+    #[test]
+    fn test_valid_up_to() {
+        let mut v = Utf8Validator::new();
+        assert!(v.feed(&[0x41, 0xE2, 0x82]).is_ok());
+        assert_eq!(v.valid_up_to(), 1);
+        assert!(v.feed(&[0xAC]).is_ok());
+        assert_eq!(v.valid_up_to(), 4);
+        assert!(v.finish().is_ok());
+    }
+}
@@ -0,0 +1,34 @@
+//! Helpers shared by the tests of the backends.
+
+use std::prelude::v1::*;
+use Error;
+
+/// Invalid and incomplete sequences to splice into valid text.
+const SEQUENCES: &[&[u8]] = &[
+    b"",
+    b"\x80",
+    b"\xC0\x80",
+    b"\xE2\x82",
+    b"\xED\xA0\x80",
+    b"\xF0\x90\x80",
+    b"\xF0\x90\x80\x80\x80\x80",
+    b"\xF4\x90\x80\x80",
+    b"\xFF",
+];
+
+/// Compares `f` against `is_utf8` on mixed ASCII and multi-byte text
+/// repeated `n` times, with each of `SEQUENCES` spliced in at every offset.
+pub fn test_splices<F>(n: usize, f: F)
+where
+    F: Fn(&[u8]) -> Result<(), Error>,
+{
+    let text = "a κόσμε \u{10348} \u{20AC}".repeat(n);
+    for seq in SEQUENCES {
+        for i in 0..=text.len() {
+            let mut x = text.as_bytes()[..i].to_vec();
+            x.extend_from_slice(seq);
+            x.extend_from_slice(&text.as_bytes()[i..]);
+            assert_eq!(f(&x), ::is_utf8(&x), "{:x?}", x);
+        }
+    }
+}
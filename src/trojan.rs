@@ -0,0 +1,91 @@
+//! Detection of Trojan Source attacks (CVE-2021-42574), which hide or
+//! reorder source code with invisible characters.
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use Error;
+
+/// Returns `true` if `c` is a bidirectional embedding, override or isolate
+/// control (`U+202A..=U+202E`, `U+2066..=U+2069`), or a zero-width
+/// character (`U+200B..=U+200D`, `U+2060`, `U+FEFF`).
+pub fn is_trojan_source_char(c: char) -> bool {
+    matches!(
+        c,
+        '\u{202A}'..='\u{202E}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{200B}'..='\u{200D}'
+            | '\u{2060}'
+            | '\u{FEFF}'
+    )
+}
+
+/// Validates `x`, calling `f` with the offset and the character of every
+/// character for which `is_trojan_source_char` is `true`, in the same pass.
+///
+/// If `x` is invalid, `f` is only called for the characters before the
+/// error.
+pub fn validate_trojan_source<F>(x: &[u8], mut f: F) -> Result<(), Error>
+where
+    F: FnMut(usize, char),
+{
+    ::rustc::validate(x, false, |i, c| {
+        if is_trojan_source_char(c) {
+            f(i, c);
+        }
+        Ok(())
+    })
+}
+
+/// Validates `x` and returns the offsets of the characters for which
+/// `is_trojan_source_char` is `true`.
+#[cfg(feature = "alloc")]
+pub fn trojan_source_offsets(x: &[u8]) -> Result<Vec<usize>, Error> {
+    let mut offsets = Vec::new();
+    validate_trojan_source(x, |i, _| offsets.push(i))?;
+    Ok(offsets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::prelude::v1::*;
+
+    #[test]
+    fn test_validate_trojan_source() {
+        // The example of the paper: an early return hidden in a comment.
+        let x = "/* \u{202E} } \u{2066}if (isAdmin)\u{2069} \u{2066} begin \
+                 admins only */\n";
+        let mut found = vec![];
+        validate_trojan_source(x.as_bytes(), |i, c| found.push((i, c)))
+            .unwrap();
+        let expected: Vec<_> = x
+            .char_indices()
+            .filter(|&(_, c)| is_trojan_source_char(c))
+            .collect();
+        assert_eq!(found.len(), 4);
+        assert_eq!(found, expected);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn test_trojan_source_offsets() {
+        let x = "a\u{200B}b\u{FEFF}κόσμε \u{10348}".repeat(3);
+        let expected: Vec<_> = x
+            .char_indices()
+            .filter(|&(_, c)| is_trojan_source_char(c))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(expected.len(), 6);
+        assert_eq!(trojan_source_offsets(x.as_bytes()), Ok(expected));
+        assert_eq!(trojan_source_offsets(b"safe"), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let x = b"\xE2\x80\x8B\xC0\x80\xE2\x80\xAE";
+        let mut found = vec![];
+        let r = validate_trojan_source(x, |i, _| found.push(i));
+        assert_eq!(r, ::is_utf8(x));
+        assert_eq!(found, [0]);
+    }
+}